@@ -0,0 +1,93 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use monkey_rust::{eval::Program, lexer::Lexer, parser::Parser};
+
+const FIB: &str = "
+    let fib = fn(x) {
+        if (x <= 2) {
+            1
+        } else {
+            fib(x - 1) + fib(x - 2)
+        }
+    };
+    fib(20);
+";
+
+const MAP_REDUCE: &str = "
+    let map = fn(arr, f) {
+        let iter = fn(arr, accumulated) {
+            if (len(arr) == 0) {
+                accumulated
+            } else {
+                iter(rest(arr), push(accumulated, f(first(arr))));
+            }
+        };
+        iter(arr, []);
+    };
+    let reduce = fn(arr, initial, f) {
+        let iter = fn(arr, result) {
+            if (len(arr) == 0) {
+                result
+            } else {
+                iter(rest(arr), f(result, first(arr)));
+            }
+        };
+        iter(arr, initial);
+    };
+    let range = fn(n, arr) { if (n == 0) { arr } else { range(n - 1, push(arr, n)) } };
+    let numbers = range(200, []);
+    reduce(map(numbers, fn(x) { x * 2 }), 0, fn(acc, x) { acc + x });
+";
+
+const HASH: &str = r#"
+    let table = {"one": 1, "two": 2, "three": 3, "four": 4, "five": 5, 6: "six", true: 7};
+    let keys = ["one", "two", "three", "four", "five", "missing"];
+    let lookup = fn(keys, acc) {
+        if (len(keys) == 0) {
+            acc
+        } else {
+            let value = table[first(keys)];
+            lookup(rest(keys), if (value == nil) { acc } else { acc + value })
+        }
+    };
+    let point = fn(n) { {"x": n, "y": n * 2, "name": "point"} };
+    let repeat = fn(n, acc) {
+        if (n == 0) {
+            acc
+        } else {
+            repeat(n - 1, acc + lookup(keys, 0) + point(n)["y"] + table[true])
+        }
+    };
+    repeat(200, 0);
+"#;
+
+fn large_input() -> String {
+    [FIB, MAP_REDUCE, HASH].concat().repeat(100)
+}
+
+fn bench_frontend(c: &mut Criterion) {
+    let input = large_input();
+    let mut group = c.benchmark_group("frontend");
+    group.throughput(Throughput::Bytes(input.len() as u64));
+    group.bench_function("lexer", |b| b.iter(|| Lexer::new(&input)));
+    group.bench_function("parser", |b| {
+        b.iter_batched(|| Lexer::new(&input), Parser::new, BatchSize::SmallInput)
+    });
+    group.finish();
+}
+
+fn bench_eval(c: &mut Criterion) {
+    let mut group = c.benchmark_group("eval");
+    for (name, input) in [("fib", FIB), ("map_reduce", MAP_REDUCE), ("hash", HASH)] {
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                || Parser::new(Lexer::new(input)),
+                |parser| Program::new().eval(parser).unwrap(),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_frontend, bench_eval);
+criterion_main!(benches);
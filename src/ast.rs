@@ -1,21 +1,28 @@
+use std::rc::Rc;
+
+use crate::resolver::Binding;
 use crate::token::{Identifier, Token};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use smol_str::SmolStr;
 
-#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum Statement {
     Let {
         identifier: Identifier,
         value: Box<Expression>,
+        //filled in by the resolver, enum fields can't be private so it is only hidden
+        #[doc(hidden)]
+        #[serde(skip)]
+        slot: Option<usize>,
     },
     Return(Box<Expression>),
     Expression(Box<Expression>),
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum Expression {
     Literal(Literal),
-    Identifier(Identifier),
+    Identifier(IdentifierExpression),
     BinaryExp(BinaryExpression),
     UnaryExpression(UnaryExpression),
     If(IfExpression),
@@ -24,7 +31,7 @@ pub enum Expression {
     Call(CallExpression),
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum BinaryOperator {
     Eq,
     NotEq,
@@ -38,51 +45,74 @@ pub enum BinaryOperator {
     Div,
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum UnaryOperator {
     Not,
     Minus,
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct IdentifierExpression {
+    pub ident: Identifier,
+    //filled in by the resolver
+    #[serde(skip)]
+    pub(crate) binding: Option<Binding>,
+}
+
+impl IdentifierExpression {
+    pub fn new(ident: Identifier) -> Self {
+        Self {
+            ident,
+            binding: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct UnaryExpression {
     pub value: Box<Expression>,
     pub operator: UnaryOperator,
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct CallExpression {
     pub arguments: Vec<Expression>,
     pub function: Box<Expression>,
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct IndexExpression {
     pub container: Box<Expression>,
     pub index: Box<Expression>,
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct FunctionExpression {
     pub parameters: Vec<Identifier>,
-    pub body: BlockStatement,
+    //shared with every function object created from it instead of cloned on each call
+    pub body: Rc<BlockStatement>,
+    //the body as written, when the optimizer replaced `body`, so functions print their source
+    #[doc(hidden)]
+    #[serde(skip)]
+    pub source: Option<Rc<BlockStatement>>,
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct IfExpression {
     pub condition: Box<Expression>,
     pub consequence: BlockStatement,
     pub alternative: Option<BlockStatement>,
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct BinaryExpression {
     pub operator: BinaryOperator,
     pub lhs: Box<Expression>,
     pub rhs: Box<Expression>,
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Serialize, Default)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, Default)]
 pub struct BlockStatement(pub Vec<Statement>);
 
 impl BlockStatement {
@@ -97,7 +127,7 @@ impl Expression {
     }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum Literal {
     Int(i64),
     String(SmolStr),
@@ -1,18 +1,418 @@
-fn main() {
-    // let mut hashtable: HashMap<i32, i32> = HashMap::new();
-    // hashtable.insert(1, 10);
-    // hashtable.insert(2, 20);
-
-    // let raw_pointer = hashtable.as_ptr();
-    let myvec = vec![1, 2];
-    match myvec.as_slice() {
-        [first] => {
-            println!("{}", first)
-        }
-        _ => println!("hello"),
+use std::{
+    cell::RefCell,
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+    process::ExitCode,
+    rc::Rc,
+};
+
+use anyhow::{Context, Result};
+use clap::{arg, value_parser, ArgMatches, Command};
+use monkey_rust::{
+    ast::{BlockStatement, Expression, Literal, Statement},
+    formatter,
+    interpreter::ParseError,
+    lexer::{Lexer, Span},
+    lint,
+    parser::{NodeSpans, Parser},
+    Interpreter, IntoObject,
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+//exit codes from sysexits.h
+const EX_DATAERR: u8 = 65;
+const EX_NOINPUT: u8 = 66;
+const EX_SOFTWARE: u8 = 70;
+
+fn cli() -> Command {
+    Command::new("monkey")
+        .about("Tools for the Monkey programming language")
+        .subcommand_required(true)
+        .arg_required_else_help(true)
+        .subcommand(
+            Command::new("run")
+                .about("Runs a Monkey script or a compiled `.mkc` file, its arguments are in the global `args` array")
+                .arg(arg!(--"dump-ast" "Print the syntax tree to stderr before running it"))
+                .arg(arg!(--"no-optimize" "Run the syntax tree as parsed, without folding constants"))
+                .arg(arg!(<FILE> "Script to run").value_parser(value_parser!(PathBuf)))
+                .arg(
+                    arg!([ARGS] ... "Arguments passed to the script")
+                        .trailing_var_arg(true)
+                        .allow_hyphen_values(true),
+                ),
+        )
+        .subcommand(
+            Command::new("compile")
+                .about("Compiles a Monkey script to a `.mkc` file that `run` accepts in place of the source")
+                .arg(arg!(-o --output <OUT> "File to write, the script with a `.mkc` extension by default").value_parser(value_parser!(PathBuf)))
+                .arg(arg!(<FILE> "Script to compile").value_parser(value_parser!(PathBuf))),
+        )
+        .subcommand(dump_command("tokens", "Include where each token is in the source").about("Prints the tokens of a Monkey file"))
+        .subcommand(dump_command(
+            "ast",
+            "Include where each statement and expression is in the source",
+        ).about("Prints the syntax tree of a Monkey file"))
+        .subcommand(
+            Command::new("fmt")
+                .about("Formats Monkey files in place")
+                .arg(arg!(--check "Only report the files that aren't formatted"))
+                .arg(arg!(<FILES> ... "Files to format").value_parser(value_parser!(PathBuf))),
+        )
+        .subcommand(
+            Command::new("lint")
+                .about("Reports code in Monkey files that is likely a mistake")
+                .after_help("Exits with 1 when there are findings, 65 when a file doesn't parse")
+                .arg(arg!(<FILES> ... "Files to check").value_parser(value_parser!(PathBuf))),
+        )
+}
+
+fn dump_command(name: &'static str, spans: &'static str) -> Command {
+    Command::new(name)
+        .arg(arg!(<FILE> "File to read").value_parser(value_parser!(PathBuf)))
+        .arg(
+            arg!(--format <FORMAT> "Output format")
+                .value_parser(["json", "yaml"])
+                .default_value("json"),
+        )
+        .arg(arg!(--"with-spans").help(spans))
+}
+
+fn main() -> ExitCode {
+    let matches = cli().get_matches();
+    let result = match matches.subcommand() {
+        Some(("run", matches)) => Ok(run(matches)),
+        Some(("compile", matches)) => Ok(compile(matches)),
+        Some(("tokens", matches)) => tokens(matches),
+        Some(("ast", matches)) => ast(matches),
+        Some(("fmt", matches)) => fmt(matches),
+        Some(("lint", matches)) => Ok(lint(matches)),
+        _ => unreachable!("a subcommand is required"),
+    };
+    result.unwrap_or_else(|err| {
+        eprintln!("error: {:?}", err);
+        ExitCode::FAILURE
+    })
+}
+
+fn run(matches: &ArgMatches) -> ExitCode {
+    let file = matches
+        .get_one::<PathBuf>("FILE")
+        .expect("FILE is required");
+    let args: Vec<String> = matches
+        .get_many::<String>("ARGS")
+        .into_iter()
+        .flatten()
+        .cloned()
+        .collect();
+
+    let result = if file.extension().is_some_and(|x| x == "mkc") {
+        Interpreter::run_compiled_file(file, vec![args.into_object()])
+    } else {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_optimize(!matches.get_flag("no-optimize"));
+        if matches.get_flag("dump-ast") {
+            interpreter.dump_ast_to(Rc::new(RefCell::new(io::stderr())));
+        }
+        interpreter.set_global("args", args.into_object());
+        interpreter.eval_file(file)
+    };
+    exit_code(result.map(drop))
+}
+
+fn compile(matches: &ArgMatches) -> ExitCode {
+    let file = matches
+        .get_one::<PathBuf>("FILE")
+        .expect("FILE is required");
+    let out = matches
+        .get_one::<PathBuf>("output")
+        .cloned()
+        .unwrap_or_else(|| file.with_extension("mkc"));
+    //`run` gives compiled scripts their arguments the same way it does to the source
+    exit_code(Interpreter::compile_file(file, out, &["args"]))
+}
+
+fn exit_code(result: Result<()>) -> ExitCode {
+    let Err(err) = result else {
+        return ExitCode::SUCCESS;
+    };
+    eprintln!("error: {:?}", err);
+    ExitCode::from(if err.downcast_ref::<io::Error>().is_some() {
+        EX_NOINPUT
+    } else if err.downcast_ref::<ParseError>().is_some() {
+        EX_DATAERR
+    } else {
+        EX_SOFTWARE
+    })
+}
+
+#[derive(Serialize)]
+struct Spanned<'a, T> {
+    node: &'a T,
+    span: Span,
+}
+
+fn read_source(matches: &ArgMatches) -> Result<String> {
+    let file = matches
+        .get_one::<PathBuf>("FILE")
+        .expect("FILE is required");
+    fs::read_to_string(file).with_context(|| format!("could not read {}", file.display()))
+}
+
+fn dump<T: Serialize>(
+    out: &mut impl Write,
+    matches: &ArgMatches,
+    nodes: &[T],
+    spans: &[Span],
+) -> Result<()> {
+    let format = matches.get_one::<String>("format").map(String::as_str);
+    if matches.get_flag("with-spans") {
+        let nodes: Vec<_> = nodes
+            .iter()
+            .zip(spans)
+            .map(|(node, span)| Spanned { node, span: *span })
+            .collect();
+        write_as(out, format, &nodes)
+    } else {
+        write_as(out, format, &nodes)
+    }
+}
+
+fn write_as(out: &mut impl Write, format: Option<&str>, value: &impl Serialize) -> Result<()> {
+    if format == Some("yaml") {
+        //serde_yaml can't write enums nested in other enums, their JSON form has no such limit
+        serde_yaml::to_writer(out, &serde_json::to_value(value)?)?;
+    } else {
+        serde_json::to_writer_pretty(&mut *out, value)?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+fn dump_ast(out: &mut impl Write, matches: &ArgMatches, parser: &Parser) -> Result<()> {
+    let format = matches.get_one::<String>("format").map(String::as_str);
+    if !matches.get_flag("with-spans") {
+        return write_as(out, format, &parser.nodes);
+    }
+    let spans = &parser.node_spans;
+    let nodes: Vec<_> = parser
+        .nodes
+        .iter()
+        .map(|x| spanned_statement(x, spans))
+        .collect::<Result<_>>()?;
+    write_as(out, format, &nodes)
+}
+
+//a node and the nodes nested in it dumped like `Spanned`, from the plain dump of the node
+fn spanned_statement(statement: &Statement, spans: &NodeSpans) -> Result<Value> {
+    let mut node = serde_json::to_value(statement)?;
+    match statement {
+        Statement::Let { value, .. } => node["Let"]["value"] = spanned_expression(value, spans)?,
+        Statement::Return(value) => node["Return"] = spanned_expression(value, spans)?,
+        Statement::Expression(value) => node["Expression"] = spanned_expression(value, spans)?,
+    }
+    Ok(json!({ "node": node, "span": spans.statement(statement) }))
+}
+
+fn spanned_expression(expression: &Expression, spans: &NodeSpans) -> Result<Value> {
+    let list = |expressions: &[Expression]| -> Result<Value> {
+        expressions
+            .iter()
+            .map(|x| spanned_expression(x, spans))
+            .collect()
     };
+    let block = |block: &BlockStatement| -> Result<Value> {
+        block
+            .0
+            .iter()
+            .map(|x| spanned_statement(x, spans))
+            .collect()
+    };
+    let mut node = serde_json::to_value(expression)?;
+    match expression {
+        Expression::Literal(Literal::Array(items)) => node["Literal"]["Array"] = list(items)?,
+        Expression::Literal(Literal::Hash(pairs)) => {
+            node["Literal"]["Hash"] = pairs
+                .iter()
+                .map(|(key, value)| {
+                    let key = spanned_expression(key, spans)?;
+                    Ok(json!([key, spanned_expression(value, spans)?]))
+                })
+                .collect::<Result<_>>()?
+        }
+        Expression::Literal(_) | Expression::Identifier(_) => {}
+        Expression::BinaryExp(exp) => {
+            node["BinaryExp"]["lhs"] = spanned_expression(&exp.lhs, spans)?;
+            node["BinaryExp"]["rhs"] = spanned_expression(&exp.rhs, spans)?;
+        }
+        Expression::UnaryExpression(exp) => {
+            node["UnaryExpression"]["value"] = spanned_expression(&exp.value, spans)?
+        }
+        Expression::If(exp) => {
+            node["If"]["condition"] = spanned_expression(&exp.condition, spans)?;
+            node["If"]["consequence"] = block(&exp.consequence)?;
+            if let Some(alternative) = &exp.alternative {
+                node["If"]["alternative"] = block(alternative)?;
+            }
+        }
+        Expression::IndexExpression(exp) => {
+            node["IndexExpression"]["container"] = spanned_expression(&exp.container, spans)?;
+            node["IndexExpression"]["index"] = spanned_expression(&exp.index, spans)?;
+        }
+        Expression::Function(function) => node["Function"]["body"] = block(&function.body)?,
+        Expression::Call(exp) => {
+            node["Call"]["function"] = spanned_expression(&exp.function, spans)?;
+            node["Call"]["arguments"] = list(&exp.arguments)?;
+        }
+    }
+    Ok(json!({ "node": node, "span": spans.expression(expression) }))
+}
+
+fn tokens(matches: &ArgMatches) -> Result<ExitCode> {
+    let lexer = Lexer::new(&read_source(matches)?);
+    dump(
+        &mut io::stdout().lock(),
+        matches,
+        &lexer.tokens,
+        &lexer.spans,
+    )?;
+    Ok(ExitCode::SUCCESS)
+}
+
+//the statements that did parse are still printed, the errors go to stderr
+fn ast(matches: &ArgMatches) -> Result<ExitCode> {
+    let parser = Parser::new(Lexer::new(&read_source(matches)?));
+    dump_ast(&mut io::stdout().lock(), matches, &parser)?;
+    for error in &parser.errors {
+        eprintln!("error: {}", error);
+    }
+    Ok(if parser.errors.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(EX_DATAERR)
+    })
+}
+
+fn fmt(matches: &ArgMatches) -> Result<ExitCode> {
+    let check = matches.get_flag("check");
+    let mut unformatted = 0;
+    for file in matches.get_many::<PathBuf>("FILES").into_iter().flatten() {
+        let source = fs::read_to_string(file)
+            .with_context(|| format!("could not read {}", file.display()))?;
+        let formatted = formatter::format(&source).with_context(|| file.display().to_string())?;
+        if formatted == source {
+            continue;
+        }
+        if check {
+            println!("{} is not formatted", file.display());
+            unformatted += 1;
+        } else {
+            fs::write(file, formatted)
+                .with_context(|| format!("could not write {}", file.display()))?;
+        }
+    }
+    Ok(if unformatted > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+//findings exit with 1, unlike files that can't be read or parsed
+fn lint(matches: &ArgMatches) -> ExitCode {
+    let mut found = 0;
+    let mut files = matches.get_many::<PathBuf>("FILES").into_iter().flatten();
+    let result = files.try_for_each(|file| -> Result<()> {
+        let source = fs::read_to_string(file)
+            .with_context(|| format!("could not read {}", file.display()))?;
+        let lints = lint::lint(&source).with_context(|| file.display().to_string())?;
+        for lint in &lints {
+            println!("{}:{}", file.display(), lint);
+        }
+        found += lints.len();
+        Ok(())
+    });
+    match result {
+        Ok(()) if found > 0 => ExitCode::FAILURE,
+        result => exit_code(result),
+    }
 }
 
-trait MyTrait {
-    fn my_method(&self);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dump_nested_nodes_with_spans() {
+        let source = "let f = fn(x) {\n  [x]\n};\nf(-1, {\"a\": 1})\n";
+        let parser = Parser::new(Lexer::new(source));
+        let matches = cli().get_matches_from(["monkey", "ast", "--with-spans", "test.mk"]);
+        let (_, matches) = matches.subcommand().unwrap();
+        let mut out = Vec::new();
+        dump_ast(&mut out, matches, &parser).unwrap();
+
+        let dumped: Vec<Value> = serde_json::from_slice(&out).unwrap();
+        let text = |x: &Value| {
+            let offset = |key: &str| x["span"][key].as_u64().unwrap() as usize;
+            &source[offset("start")..offset("end")]
+        };
+        let statements: Vec<_> = dumped.iter().map(text).collect();
+        assert_eq!(
+            statements,
+            ["let f = fn(x) {\n  [x]\n};", "f(-1, {\"a\": 1})"]
+        );
+        let function = &dumped[0]["node"]["Let"]["value"];
+        assert_eq!(text(function), "fn(x) {\n  [x]\n}");
+        let body = &function["node"]["Function"]["body"][0];
+        let array = &body["node"]["Expression"];
+        assert_eq!(text(array), "[x]");
+        assert_eq!(
+            array["node"]["Literal"]["Array"][0]["node"]["Identifier"],
+            "x"
+        );
+
+        let call = &dumped[1]["node"]["Expression"]["node"]["Call"];
+        assert_eq!(text(&call["function"]), "f");
+        let arguments: Vec<_> = call["arguments"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(text)
+            .collect();
+        assert_eq!(arguments, ["-1", "{\"a\": 1}"]);
+        let pair = &call["arguments"][1]["node"]["Literal"]["Hash"][0];
+        assert_eq!((text(&pair[0]), text(&pair[1])), ("\"a\"", "1"));
+
+        let matches = cli().get_matches_from(["monkey", "ast", "test.mk"]);
+        let mut out = Vec::new();
+        dump_ast(&mut out, matches.subcommand().unwrap().1, &parser).unwrap();
+        let dumped: Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(dumped, serde_json::to_value(&parser.nodes).unwrap());
+    }
+
+    #[test]
+    fn exit_with_lint_findings_apart_from_parse_errors() {
+        let dir = std::env::temp_dir().join(format!("monkey-lint-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let lint_files = |sources: &[&str]| {
+            let files: Vec<_> = (0..sources.len())
+                .map(|x| dir.join(format!("{}.mk", x)))
+                .collect();
+            for (file, source) in files.iter().zip(sources) {
+                fs::write(file, source).unwrap();
+            }
+            let args = ["monkey", "lint"].map(PathBuf::from);
+            let matches = cli().get_matches_from(args.into_iter().chain(files));
+            lint(matches.subcommand().unwrap().1)
+        };
+
+        assert_eq!(lint_files(&["puts(1)"]), ExitCode::SUCCESS);
+        assert_eq!(lint_files(&["let a = 1;"]), ExitCode::FAILURE);
+        assert_eq!(
+            lint_files(&["let a = 1;", "let = 1;"]),
+            ExitCode::from(EX_DATAERR)
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,439 @@
+//! Language server for Monkey, speaking LSP over stdio.
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, RequestId, Response};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+        Notification as NotificationType, PublishDiagnostics,
+    },
+    request::{
+        DocumentSymbolRequest, Formatting, GotoDefinition, HoverRequest, Request as RequestType,
+    },
+    Diagnostic, DiagnosticSeverity, DocumentFormattingParams, DocumentSymbol, DocumentSymbolParams,
+    DocumentSymbolResponse, GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverContents,
+    HoverParams, HoverProviderCapability, Location, MarkupContent, MarkupKind, OneOf, Position,
+    PublishDiagnosticsParams, Range, ServerCapabilities, SymbolKind, TextDocumentSyncCapability,
+    TextDocumentSyncKind, TextEdit, Url,
+};
+use monkey_rust::{
+    formatter,
+    lexer::{Lexer, Span},
+    object::BuiltInFn,
+    parser::Parser,
+    symbols::{DefinitionKind, Symbols},
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+fn main() -> Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+    serve(&connection)?;
+    //the writer thread only stops once the connection is gone
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
+
+fn serve(connection: &Connection) -> Result<()> {
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        ..Default::default()
+    };
+    connection.initialize(serde_json::to_value(capabilities)?)?;
+
+    let mut server = Server::default();
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    return Ok(());
+                }
+                connection.sender.send(server.respond(request).into())?;
+            }
+            Message::Notification(notification) => {
+                if let Some(uri) = server.update(notification)? {
+                    let diagnostics = server.diagnostics(&uri);
+                    let params = PublishDiagnosticsParams::new(uri, diagnostics, None);
+                    let notification = Notification::new(PublishDiagnostics::METHOD.into(), params);
+                    connection.sender.send(notification.into())?;
+                }
+            }
+            Message::Response(_) => {}
+        }
+    }
+    Ok(())
+}
+
+//converts between byte offsets and LSP positions, whose columns count UTF-16 code units
+struct LineIndex<'a> {
+    source: &'a str,
+    starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    fn new(source: &'a str) -> Self {
+        let newlines = source.match_indices('\n').map(|(index, _)| index + 1);
+        Self {
+            source,
+            starts: std::iter::once(0).chain(newlines).collect(),
+        }
+    }
+
+    fn position(&self, offset: usize) -> Position {
+        let line = self.starts.partition_point(|x| *x <= offset) - 1;
+        let column = self.source[self.starts[line]..offset]
+            .encode_utf16()
+            .count();
+        Position::new(line as u32, column as u32)
+    }
+
+    fn offset(&self, position: Position) -> Option<usize> {
+        let start = *self.starts.get(position.line as usize)?;
+        let mut column = 0;
+        for (index, char) in self.source[start..].char_indices() {
+            if column >= position.character as usize || char == '\n' {
+                return Some(start + index);
+            }
+            column += char.len_utf16();
+        }
+        Some(self.source.len())
+    }
+
+    fn range(&self, span: Span) -> Range {
+        Range::new(self.position(span.start), self.position(span.end))
+    }
+}
+
+#[derive(Default)]
+struct Server {
+    documents: HashMap<Url, String>,
+}
+
+impl Server {
+    fn respond(&self, request: Request) -> Response {
+        let id = request.id.clone();
+        let result = match request.method.as_str() {
+            GotoDefinition::METHOD => handle(request, |x| self.definition(x)),
+            HoverRequest::METHOD => handle(request, |x| self.hover(x)),
+            DocumentSymbolRequest::METHOD => handle(request, |x| self.symbols(x)),
+            Formatting::METHOD => handle(request, |x: DocumentFormattingParams| {
+                self.format(x.text_document.uri)
+            }),
+            method => {
+                let message = format!("unsupported request {}", method);
+                return Response::new_err(id, ErrorCode::MethodNotFound as i32, message);
+            }
+        };
+        result.unwrap_or_else(|err| {
+            Response::new_err(id, ErrorCode::RequestFailed as i32, err.to_string())
+        })
+    }
+
+    //the document whose diagnostics changed, if any
+    fn update(&mut self, notification: Notification) -> Result<Option<Url>> {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params = notification
+                    .extract::<<DidOpenTextDocument as NotificationType>::Params>(
+                        DidOpenTextDocument::METHOD,
+                    )?;
+                let document = params.text_document;
+                self.documents.insert(document.uri.clone(), document.text);
+                Ok(Some(document.uri))
+            }
+            DidChangeTextDocument::METHOD => {
+                let params = notification
+                    .extract::<<DidChangeTextDocument as NotificationType>::Params>(
+                        DidChangeTextDocument::METHOD,
+                    )?;
+                //only full syncs were asked for, so the last change holds the whole text
+                let Some(change) = params.content_changes.into_iter().last() else {
+                    return Ok(None);
+                };
+                let uri = params.text_document.uri;
+                self.documents.insert(uri.clone(), change.text);
+                Ok(Some(uri))
+            }
+            DidCloseTextDocument::METHOD => {
+                let params = notification
+                    .extract::<<DidCloseTextDocument as NotificationType>::Params>(
+                        DidCloseTextDocument::METHOD,
+                    )?;
+                self.documents.remove(&params.text_document.uri);
+                Ok(None)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn document(&self, uri: &Url) -> Result<&str> {
+        self.documents
+            .get(uri)
+            .map(String::as_str)
+            .ok_or_else(|| anyhow!("{} isn't open", uri))
+    }
+
+    fn diagnostics(&self, uri: &Url) -> Vec<Diagnostic> {
+        let Ok(source) = self.document(uri) else {
+            return Vec::new();
+        };
+        let index = LineIndex::new(source);
+        let diagnostic = |span, message| Diagnostic {
+            range: index.range(span),
+            severity: Some(DiagnosticSeverity::ERROR),
+            source: Some("monkey".into()),
+            message,
+            ..Default::default()
+        };
+
+        let parser = Parser::new(Lexer::new(source));
+        parser
+            .errors
+            .iter()
+            .zip(parser.error_spans)
+            .map(|(error, span)| diagnostic(span, error.to_string()))
+            .collect()
+    }
+
+    fn definition(&self, params: GotoDefinitionParams) -> Result<Option<GotoDefinitionResponse>> {
+        let position = params.text_document_position_params;
+        let uri = position.text_document.uri;
+        let source = self.document(&uri)?;
+        let index = LineIndex::new(source);
+        let Some(offset) = index.offset(position.position) else {
+            return Ok(None);
+        };
+
+        let symbols = Symbols::new(&Lexer::new(source));
+        Ok(symbols.definition_at(offset).map(|x| {
+            let range = index.range(symbols.definitions[x].span);
+            GotoDefinitionResponse::Scalar(Location::new(uri, range))
+        }))
+    }
+
+    fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+        let position = params.text_document_position_params;
+        let source = self.document(&position.text_document.uri)?;
+        let index = LineIndex::new(source);
+        let Some(offset) = index.offset(position.position) else {
+            return Ok(None);
+        };
+
+        let symbols = Symbols::new(&Lexer::new(source));
+        let Some(reference) = symbols.reference_at(offset) else {
+            return Ok(None);
+        };
+        let builtin = match reference.definition {
+            None => BuiltInFn::from_name(reference.name.as_str()),
+            Some(_) => None,
+        };
+        Ok(builtin.map(|builtin| Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: format!("```monkey\n{}\n```\n{}", builtin.signature(), builtin.doc()),
+            }),
+            range: Some(index.range(reference.span)),
+        }))
+    }
+
+    //every `let` of the document, whole statements for the global ones
+    fn symbols(&self, params: DocumentSymbolParams) -> Result<Option<DocumentSymbolResponse>> {
+        let source = self.document(&params.text_document.uri)?;
+        let index = LineIndex::new(source);
+        let lexer = Lexer::new(source);
+        let symbols = Symbols::new(&lexer);
+        let statements = Parser::new(lexer).spans;
+
+        let symbols = symbols
+            .definitions
+            .iter()
+            .filter(|x| x.kind == DefinitionKind::Let)
+            .map(|definition| {
+                let statement = statements
+                    .iter()
+                    .filter(|_| definition.scope == 0)
+                    .find(|x| x.start <= definition.span.start && definition.span.end <= x.end);
+                #[allow(deprecated)]
+                DocumentSymbol {
+                    name: definition.name.to_string(),
+                    detail: None,
+                    kind: if definition.function {
+                        SymbolKind::FUNCTION
+                    } else {
+                        SymbolKind::VARIABLE
+                    },
+                    tags: None,
+                    deprecated: None,
+                    range: index.range(*statement.unwrap_or(&definition.span)),
+                    selection_range: index.range(definition.span),
+                    children: None,
+                }
+            });
+        Ok(Some(DocumentSymbolResponse::Nested(symbols.collect())))
+    }
+
+    //no edits for a document that doesn't parse, its errors are already reported
+    fn format(&self, uri: Url) -> Result<Option<Vec<TextEdit>>> {
+        let source = self.document(&uri)?;
+        let Ok(formatted) = formatter::format(source) else {
+            return Ok(None);
+        };
+        if formatted == source {
+            return Ok(Some(Vec::new()));
+        }
+        let index = LineIndex::new(source);
+        let range = Range::new(Position::new(0, 0), index.position(source.len()));
+        Ok(Some(vec![TextEdit::new(range, formatted)]))
+    }
+}
+
+fn handle<P: DeserializeOwned, R: Serialize>(
+    request: Request,
+    handler: impl FnOnce(P) -> Result<R>,
+) -> Result<Response> {
+    let id: RequestId = request.id;
+    let params = serde_json::from_value(request.params)?;
+    Ok(Response::new_ok(id, handler(params)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::{
+        request::Shutdown, DidOpenTextDocumentParams, InitializeParams, TextDocumentIdentifier,
+        TextDocumentItem, TextDocumentPositionParams,
+    };
+    use serde_json::{json, Value};
+    use std::thread;
+
+    fn request<R: RequestType>(client: &Connection, id: i32, params: R::Params) -> Value {
+        let request = Request::new(id.into(), R::METHOD.into(), params);
+        client.sender.send(request.into()).unwrap();
+        match client.receiver.recv().unwrap() {
+            Message::Response(response) => response.result.unwrap_or(Value::Null),
+            message => panic!("expected a response, got {:?}", message),
+        }
+    }
+
+    fn position(uri: &Url, line: u32, character: u32) -> TextDocumentPositionParams {
+        TextDocumentPositionParams {
+            text_document: TextDocumentIdentifier::new(uri.clone()),
+            position: Position::new(line, character),
+        }
+    }
+
+    #[test]
+    fn answer_editor_requests() {
+        let (server, client) = Connection::memory();
+        let server = thread::spawn(move || serve(&server).unwrap());
+        let initialize = Request::new(0.into(), "initialize".into(), InitializeParams::default());
+        client.sender.send(initialize.into()).unwrap();
+        client.receiver.recv().unwrap();
+        let initialized = Notification::new("initialized".into(), json!({}));
+        client.sender.send(initialized.into()).unwrap();
+
+        let uri = Url::parse("file:///test.mk").unwrap();
+        let text = "let add = fn(a, b) { a + b };\nlet x = len(add(1, y))\n";
+        let open = DidOpenTextDocumentParams {
+            text_document: TextDocumentItem::new(uri.clone(), "monkey".into(), 1, text.into()),
+        };
+        let open = Notification::new(DidOpenTextDocument::METHOD.into(), open);
+        client.sender.send(open.into()).unwrap();
+        let Message::Notification(published) = client.receiver.recv().unwrap() else {
+            panic!("expected diagnostics");
+        };
+        let messages: Vec<_> = published.params["diagnostics"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|x| (x["range"]["start"]["line"].clone(), x["message"].clone()))
+            .collect();
+        assert_eq!(
+            messages,
+            [(
+                json!(1),
+                json!("Expected token Semicolon but found the end of file")
+            )]
+        );
+
+        let definition = request::<GotoDefinition>(
+            &client,
+            1,
+            GotoDefinitionParams {
+                text_document_position_params: position(&uri, 1, 13),
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            },
+        );
+        assert_eq!(
+            definition["range"]["start"],
+            json!({"line": 0, "character": 4})
+        );
+
+        let hover = request::<HoverRequest>(
+            &client,
+            2,
+            HoverParams {
+                text_document_position_params: position(&uri, 1, 9),
+                work_done_progress_params: Default::default(),
+            },
+        );
+        let contents = hover["contents"]["value"].as_str().unwrap();
+        assert!(
+            contents.contains("len(string | array) -> int"),
+            "{}",
+            contents
+        );
+
+        let symbols = request::<DocumentSymbolRequest>(
+            &client,
+            3,
+            DocumentSymbolParams {
+                text_document: TextDocumentIdentifier::new(uri.clone()),
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            },
+        );
+        let names: Vec<_> = symbols
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|x| (x["name"].clone(), x["kind"].clone()))
+            .collect();
+        assert_eq!(names, [(json!("add"), json!(12)), (json!("x"), json!(13))]);
+
+        request::<Shutdown>(&client, 4, ());
+        client
+            .sender
+            .send(Notification::new("exit".into(), json!(null)).into())
+            .unwrap();
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn convert_positions() {
+        let index = LineIndex::new("ab\n\"é\" c\n");
+        assert_eq!(index.position(4), Position::new(1, 1));
+        assert_eq!(index.position(8), Position::new(1, 4));
+        assert_eq!(index.offset(Position::new(1, 4)), Some(8));
+        assert_eq!(index.offset(Position::new(0, 10)), Some(2));
+        assert_eq!(index.offset(Position::new(5, 0)), None);
+        assert_eq!(
+            index
+                .range(Span {
+                    start: 0,
+                    end: 2,
+                    line: 1,
+                    column: 1
+                })
+                .end,
+            Position::new(0, 2)
+        );
+    }
+}
@@ -0,0 +1,313 @@
+use std::borrow::Cow;
+
+use monkey_rust::{
+    eval::Program,
+    lexer::Lexer,
+    object::BuiltInFn,
+    parser::{Parser, UnexpectedEof},
+    token::Token,
+};
+use rustyline::{
+    completion::Completer,
+    highlight::Highlighter,
+    hint::{Hint, Hinter},
+    validate::{ValidationContext, ValidationResult, Validator},
+    Context, Helper,
+};
+
+const KEYWORDS: [&str; 8] = ["fn", "let", "true", "false", "if", "else", "return", "nil"];
+
+const RESET: &str = "\x1b[0m";
+const KEYWORD: &str = "\x1b[35m";
+const BUILTIN: &str = "\x1b[36m";
+const NUMBER: &str = "\x1b[33m";
+const STRING: &str = "\x1b[32m";
+const ILLEGAL: &str = "\x1b[31m";
+const DIM: &str = "\x1b[90m";
+
+#[derive(Helper)]
+pub struct MonkeyHelper {
+    //shares the globals of the REPL, to complete the names they define
+    pub program: Program,
+}
+
+impl Completer for MonkeyHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let start = line[..pos]
+            .rfind(|x: char| !(x.is_ascii_alphanumeric() || x == '_'))
+            .map_or(0, |x| x + 1);
+        let word = &line[start..pos];
+        if start == 1 && line.starts_with(':') {
+            let commands = super::COMMANDS.iter().filter(|x| x.starts_with(word));
+            return Ok((start, commands.map(|x| x.to_string()).collect()));
+        }
+        if word.is_empty() || word.starts_with(|x: char| x.is_ascii_digit()) {
+            return Ok((start, Vec::new()));
+        }
+
+        let globals = self.program.globals();
+        let mut candidates: Vec<_> = KEYWORDS
+            .into_iter()
+            .chain(BuiltInFn::ALL.iter().map(BuiltInFn::name))
+            .chain(globals.iter().map(|x| x.0))
+            .filter(|x| x.starts_with(word))
+            .map(String::from)
+            .collect();
+        candidates.sort_unstable();
+        candidates.dedup();
+        Ok((start, candidates))
+    }
+}
+
+impl Highlighter for MonkeyHelper {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        let lexer = Lexer::new(line);
+        let mut out = String::with_capacity(line.len() * 2);
+        let mut end = 0;
+        for (token, span) in lexer.tokens.iter().zip(&lexer.spans) {
+            push_between_tokens(&mut out, &line[end..span.start]);
+            let text = &line[span.start..span.end];
+            match colour(token) {
+                Some(colour) => out.extend([colour, text, RESET]),
+                None => out.push_str(text),
+            }
+            end = span.end;
+        }
+        push_between_tokens(&mut out, &line[end..]);
+        Cow::Owned(out)
+    }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        Cow::Owned(format!("{DIM}{hint}{RESET}"))
+    }
+
+    //any character can change how the rest of the line lexes, a quote for instance
+    fn highlight_char(&self, _line: &str, _pos: usize) -> bool {
+        true
+    }
+}
+
+fn colour(token: &Token) -> Option<&'static str> {
+    match token {
+        Token::Function
+        | Token::Let
+        | Token::If
+        | Token::Else
+        | Token::Return
+        | Token::True
+        | Token::False
+        | Token::Nil => Some(KEYWORD),
+        Token::Identifier(name) if BuiltInFn::from_name(name.as_str()).is_some() => Some(BUILTIN),
+        Token::Int(_) => Some(NUMBER),
+        Token::String(_) => Some(STRING),
+        Token::Illegal => Some(ILLEGAL),
+        _ => None,
+    }
+}
+
+//only whitespace and comments are left between tokens, a `//` always starts a comment there
+fn push_between_tokens(out: &mut String, text: &str) {
+    for line in text.split_inclusive('\n') {
+        match line.find("//") {
+            Some(index) => {
+                let (code, comment) = line.split_at(index);
+                let comment = comment.trim_end_matches('\n');
+                out.extend([
+                    code,
+                    DIM,
+                    comment,
+                    RESET,
+                    &line[code.len() + comment.len()..],
+                ]);
+            }
+            None => out.push_str(line),
+        }
+    }
+}
+
+/// Rest of the signature of a builtin, shown after its opening parenthesis. Only a
+/// reminder, so accepting the hint doesn't insert it.
+pub struct SignatureHint(&'static str);
+
+impl Hint for SignatureHint {
+    fn display(&self) -> &str {
+        self.0
+    }
+
+    fn completion(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl Hinter for MonkeyHelper {
+    type Hint = SignatureHint;
+
+    fn hint(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> Option<SignatureHint> {
+        if pos < line.len() {
+            return None;
+        }
+        let lexer = Lexer::new(line);
+        let [.., Token::Identifier(name), Token::LParen] = lexer.tokens.as_slice() else {
+            return None;
+        };
+        let signature = BuiltInFn::from_name(name.as_str())?.signature();
+        Some(SignatureHint(&signature[name.as_str().len() + 1..]))
+    }
+}
+
+//enter only submits the input once it is whole, otherwise it starts a new line
+impl Validator for MonkeyHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        Ok(validate(ctx.input()))
+    }
+}
+
+//meta-commands that take an expression can span lines too, the others can't
+fn validate(input: &str) -> ValidationResult {
+    let input = input.trim_start();
+    let code = match input.strip_prefix(':') {
+        Some(command) => match command.split_once(char::is_whitespace) {
+            Some(("type" | "ast" | "time", expr)) => expr,
+            _ => "",
+        },
+        None => input,
+    };
+    match is_incomplete(code) {
+        true => ValidationResult::Incomplete,
+        false => ValidationResult::Valid(None),
+    }
+}
+
+/// Whether more lines could turn `input` into valid code: a delimiter or a string is still
+/// open, or the parser ran out of tokens. A missing semicolon is left for the parser to
+/// report though, enter shouldn't wait for one.
+fn is_incomplete(input: &str) -> bool {
+    let lexer = Lexer::new(input);
+    let depth = lexer.tokens.iter().fold(0, |depth, token| match token {
+        Token::LParen | Token::LBrace | Token::LBracket => depth + 1,
+        Token::RParen | Token::RBrace | Token::RBracket => depth - 1,
+        _ => depth,
+    });
+    if depth > 0 {
+        return true;
+    }
+    if let (Some(Token::String(_)), Some(span)) = (lexer.tokens.last(), lexer.spans.last()) {
+        let string = &input[span.start..span.end];
+        if string.len() == 1 || !string.ends_with('"') {
+            return true;
+        }
+    }
+    Parser::new(lexer).errors.iter().any(|x| {
+        x.downcast_ref::<UnexpectedEof>()
+            .is_some_and(|x| x.0 != Some(Token::Semicolon))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use monkey_rust::object::Object;
+    use rustyline::history::DefaultHistory;
+
+    fn helper() -> MonkeyHelper {
+        let program = Program::new();
+        program.set_global("length", Object::Int(1));
+        MonkeyHelper { program }
+    }
+
+    #[test]
+    fn complete_names() {
+        let helper = helper();
+        let history = DefaultHistory::new();
+        let ctx = Context::new(&history);
+
+        let (start, candidates) = helper.complete("1 + le", 6, &ctx).unwrap();
+        assert_eq!(
+            (start, candidates),
+            (4, vec!["len".into(), "length".into(), "let".into()])
+        );
+        let (start, candidates) = helper.complete(":ty", 3, &ctx).unwrap();
+        assert_eq!((start, candidates), (1, vec!["type".into()]));
+        assert!(helper.complete("12", 2, &ctx).unwrap().1.is_empty());
+    }
+
+    #[test]
+    fn highlight_tokens() {
+        let helper = helper();
+        let line = "let s = len(\"a\") // done";
+        assert_eq!(
+            helper.highlight(line, 0),
+            "\x1b[35mlet\x1b[0m s = \x1b[36mlen\x1b[0m(\x1b[32m\"a\"\x1b[0m) \x1b[90m// done\x1b[0m"
+        );
+        let history = DefaultHistory::new();
+        let ctx = Context::new(&history);
+        let hint = helper.hint("x + push(", 9, &ctx).unwrap();
+        assert_eq!(hint.display(), "array, element) -> array");
+        assert!(helper.hint("x + push(a", 10, &ctx).is_none());
+    }
+
+    #[test]
+    fn detect_incomplete_input() {
+        let incomplete = [
+            "let add = fn(a, b) {",
+            "let add = fn(a, b) {\n  a + b\n",
+            "puts(1,",
+            "let s = \"abc",
+            "[1, {\"a\": (2",
+            "if (a) { 1 } else",
+            "let a = ",
+            "1 +",
+        ];
+        for input in incomplete {
+            assert!(is_incomplete(input), "{}", input);
+        }
+        let complete = [
+            "",
+            "1 + 1",
+            "let a = 1;",
+            "}",
+            "let = 1;",
+            "\"a\" + \"\"",
+            "let a = 1",
+            "let add = fn(a, b) {\n  a + b\n}",
+        ];
+        for input in complete {
+            assert!(!is_incomplete(input), "{}", input);
+        }
+    }
+
+    #[test]
+    fn validate_input() {
+        let incomplete = [
+            "let add = fn(a, b) {",
+            "let add = fn(a, b) {\n  a + b",
+            "if (a) { 1 } else",
+            "  :type [1,",
+            ":ast if (a) { 1 } else",
+        ];
+        for input in incomplete {
+            let result = validate(input);
+            assert!(matches!(result, ValidationResult::Incomplete), "{}", input);
+        }
+        let valid = [
+            "",
+            "let add = fn(a, b) {\n  a + b\n};",
+            "if (a) { 1 } else { 2 }",
+            "let a = 1",
+            ":type [1,\n2]",
+            ":load (",
+            ":env",
+        ];
+        for input in valid {
+            let result = validate(input);
+            assert!(matches!(result, ValidationResult::Valid(None)), "{}", input);
+        }
+    }
+}
@@ -0,0 +1,196 @@
+mod helper;
+
+use std::{cell::RefCell, fs, io, mem, path::PathBuf, rc::Rc, time::Instant};
+
+use anyhow::{bail, ensure, Context};
+use helper::MonkeyHelper;
+use monkey_rust::eval::Program;
+use monkey_rust::interpreter::ParseError;
+use monkey_rust::lexer::Lexer;
+use monkey_rust::object::{Object, NIL};
+use monkey_rust::parser::Parser;
+use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use rustyline::{Editor, Result};
+
+//meta-commands, completed after a `:`
+const COMMANDS: [&str; 7] = ["help", "env", "reset", "load", "type", "ast", "time"];
+
+//values wider than this are printed over several lines
+const WIDTH: usize = 80;
+
+const HELP: &str = "\
+:help           show this message
+:env            list the globals and their values
+:reset          forget every global
+:load <file>    evaluate a file
+:type <expr>    show the type of an expression
+:ast <expr>     show the syntax tree of an expression
+:time <expr>    evaluate an expression and show how long it took";
+
+struct Repl {
+    program: Program,
+}
+
+impl Repl {
+    fn new(dump_ast: bool) -> Self {
+        let mut program = Program::interactive();
+        if dump_ast {
+            program.dump_ast = Some(Rc::new(RefCell::new(io::stderr())));
+        }
+        Self { program }
+    }
+
+    fn run(&mut self, line: &str) -> anyhow::Result<()> {
+        let Some(command) = line.trim_start().strip_prefix(':') else {
+            let value = self.eval(line)?;
+            Self::print(value);
+            return Ok(());
+        };
+        let (name, arg) = command
+            .split_once(char::is_whitespace)
+            .map_or((command.trim(), ""), |(name, arg)| (name, arg.trim()));
+        ensure!(
+            !matches!(name, "load" | "type" | "ast" | "time") || !arg.is_empty(),
+            "missing argument, usage: :{} <{}>",
+            name,
+            if name == "load" { "file" } else { "expr" }
+        );
+
+        match name {
+            "help" => println!("{}", HELP),
+            "env" => {
+                for (name, value) in self.program.globals() {
+                    println!("{} = {}", name, value.inspect(WIDTH));
+                }
+            }
+            "reset" => *self = Self::new(self.program.dump_ast.is_some()),
+            "load" => {
+                let source =
+                    fs::read_to_string(arg).with_context(|| format!("could not read {}", arg))?;
+                let value = self.eval(&source).with_context(|| arg.to_string())?;
+                Self::print(value);
+            }
+            "type" => println!("{}", self.eval(arg)?.as_typeof()),
+            "ast" => {
+                let nodes = Self::parse(arg)?.nodes;
+                println!("{}", serde_json::to_string_pretty(&nodes)?);
+            }
+            "time" => {
+                let start = Instant::now();
+                let value = self.eval(arg)?;
+                let elapsed = start.elapsed();
+                Self::print(value);
+                println!("took {:?}", elapsed);
+            }
+            _ => bail!("unknown command :{}, try :help", name),
+        }
+        Ok(())
+    }
+
+    fn parse(source: &str) -> anyhow::Result<Parser> {
+        let mut parser = Parser::new(Lexer::new(source));
+        if !parser.errors.is_empty() {
+            return Err(ParseError(mem::take(&mut parser.errors)).into());
+        }
+        Ok(parser)
+    }
+
+    fn eval(&mut self, source: &str) -> anyhow::Result<Object> {
+        let parser = Self::parse(source)?;
+        self.program.eval(parser)
+    }
+
+    fn print(value: Object) {
+        if value != NIL {
+            println!("{}", value.inspect(WIDTH))
+        }
+    }
+}
+
+//none when there is no data dir, the history is then only kept for the session
+fn history_path() -> Option<PathBuf> {
+    let dir = dirs::data_dir()?.join("monkey");
+    fs::create_dir_all(&dir).ok()?;
+    Some(dir.join("history.txt"))
+}
+
+fn main() -> Result<()> {
+    let mut rl = Editor::<MonkeyHelper, DefaultHistory>::new()?;
+    let mut repl = Repl::new(std::env::args().any(|x| x == "--dump-ast"));
+    rl.set_helper(Some(MonkeyHelper {
+        program: repl.program.clone(),
+    }));
+    let history = history_path();
+    if let Some(path) = &history {
+        //there is nothing to load the first time
+        let _ = rl.load_history(path);
+    }
+
+    loop {
+        //the helper keeps reading lines until the input is whole
+        let readline = rl.readline(">> ");
+        match readline {
+            Ok(line) => {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                rl.add_history_entry(line.as_str())?;
+                if let Err(err) = repl.run(&line) {
+                    println!("error: {:?}", err);
+                }
+                //`:reset` replaces the program
+                if let Some(helper) = rl.helper_mut() {
+                    helper.program = repl.program.clone();
+                }
+            }
+            Err(ReadlineError::Interrupted) => {
+                println!("CTRL-C");
+                break;
+            }
+            Err(ReadlineError::Eof) => {
+                println!("CTRL-D");
+                break;
+            }
+            Err(err) => {
+                println!("Error: {:?}", err);
+                break;
+            }
+        }
+    }
+
+    if let Some(path) = &history {
+        if let Err(err) = rl.save_history(path) {
+            println!("could not save the history: {}", err);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_missing_semicolons() {
+        let error = Repl::new(false).run("let a = 1").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Expected token Semicolon but found the end of file"
+        );
+    }
+
+    #[test]
+    fn keep_values_of_failed_redefinitions() {
+        let mut repl = Repl::new(false);
+        repl.run("let x = 1;").unwrap();
+        let error = repl.run("let x = 1 + true;").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "operator `Add` not supported between values \"1\" and \"true\""
+        );
+        assert_eq!(repl.program.global("x"), Some(Object::Int(1)));
+        repl.run("let x = x + 1;").unwrap();
+        assert_eq!(repl.program.global("x"), Some(Object::Int(2)));
+    }
+}
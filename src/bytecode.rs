@@ -0,0 +1,374 @@
+use std::{
+    fmt::{self, Display},
+    fs,
+    path::Path,
+};
+
+use anyhow::{bail, ensure, Context, Result};
+
+use crate::code::{Instructions, Opcode};
+use crate::compiler::{Bytecode, Constant};
+use crate::object::BuiltInFn;
+
+/// First bytes of every `.mkc` file.
+pub const MAGIC: &[u8; 4] = b"MKC\0";
+/// Bumped whenever the opcodes or the encoding of the constant pool change.
+pub const FORMAT_VERSION: u16 = 2;
+
+impl Bytecode {
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(64);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bincode::serialize_into(&mut bytes, self)?;
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        ensure!(
+            bytes.len() >= MAGIC.len() + 2 && bytes.starts_with(MAGIC),
+            "not a compiled monkey file"
+        );
+        let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+        ensure!(
+            version == FORMAT_VERSION,
+            "unsupported bytecode version {}, expected {}",
+            version,
+            FORMAT_VERSION
+        );
+        let bytecode: Bytecode =
+            bincode::deserialize(&bytes[6..]).context("corrupted bytecode file")?;
+        bytecode.verify()?;
+        Ok(bytecode)
+    }
+
+    pub fn write_file(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        fs::write(path, self.to_bytes()?).with_context(|| format!("writing {}", path.display()))
+    }
+
+    pub fn read_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+        Self::from_bytes(&bytes).with_context(|| format!("loading {}", path.display()))
+    }
+
+    //the vm trusts the compiler, so anything loaded from disk is checked before it runs: every
+    //operand must refer to something that exists and every jump must land on an instruction.
+    //The stack isn't checked, the vm reports an underflow as an error
+    fn verify(&self) -> Result<()> {
+        //number of free variables each function constant is closed over with
+        let mut captured = vec![usize::MAX; self.constants.len()];
+        let free = self.verify_instructions(&self.instructions, 0, &mut captured)?;
+        ensure!(free == 0, "free variable outside of a function");
+        let mut reads = vec![0; self.constants.len()];
+        for (index, constant) in self.constants.iter().enumerate() {
+            if let Constant::Function(function) = constant {
+                //locals are addressed with one byte and parameters are the first locals
+                ensure!(
+                    function.num_locals <= u8::MAX as usize + 1,
+                    "function constant {} has {} locals, max is {}",
+                    index,
+                    function.num_locals,
+                    u8::MAX as usize + 1
+                );
+                ensure!(
+                    function.num_parameters <= function.num_locals.min(u8::MAX as usize),
+                    "function constant {} has {} parameters but {} locals",
+                    index,
+                    function.num_parameters,
+                    function.num_locals
+                );
+                reads[index] = self.verify_instructions(
+                    &function.instructions,
+                    function.num_locals,
+                    &mut captured,
+                )?;
+            }
+        }
+        for (index, (reads, captured)) in reads.into_iter().zip(captured).enumerate() {
+            ensure!(
+                reads <= captured,
+                "function constant {} reads {} free variables but only captures {}",
+                index,
+                reads,
+                captured
+            );
+        }
+        Ok(())
+    }
+
+    //returns how many free variables the instructions read
+    fn verify_instructions(
+        &self,
+        instructions: &Instructions,
+        num_locals: usize,
+        captured: &mut [usize],
+    ) -> Result<usize> {
+        let mut starts = vec![false; instructions.len() + 1];
+        let mut jumps = Vec::new();
+        let mut free = 0;
+        let mut offset = 0;
+        while offset < instructions.len() {
+            starts[offset] = true;
+            let (op, operands) = instructions.decode(offset)?;
+            match (op, operands.as_slice()) {
+                (Opcode::Constant, [index]) => ensure!(
+                    matches!(
+                        self.constants.get(*index),
+                        Some(Constant::Int(_) | Constant::String(_))
+                    ),
+                    "invalid constant {} at {:04}",
+                    index,
+                    offset
+                ),
+                (Opcode::Closure, [index, num_free]) => {
+                    ensure!(
+                        matches!(self.constants.get(*index), Some(Constant::Function(_))),
+                        "invalid function constant {} at {:04}",
+                        index,
+                        offset
+                    );
+                    captured[*index] = captured[*index].min(*num_free);
+                }
+                (Opcode::Jump | Opcode::JumpNotTruthy, [target]) => jumps.push((offset, *target)),
+                (Opcode::GetBuiltin, [index]) if *index >= BuiltInFn::ALL.len() => {
+                    bail!("unknown builtin {} at {:04}", index, offset)
+                }
+                (Opcode::GetLocal | Opcode::SetLocal, [index]) if *index >= num_locals => {
+                    bail!("invalid local {} at {:04}", index, offset)
+                }
+                (Opcode::GetFree, [index]) => free = free.max(index + 1),
+                _ => {}
+            }
+            offset += op.width();
+        }
+        starts[instructions.len()] = true;
+        for (offset, target) in jumps {
+            ensure!(
+                starts.get(target) == Some(&true),
+                "invalid jump target {:04} at {:04}",
+                target,
+                offset
+            );
+        }
+        Ok(free)
+    }
+
+    fn annotate(&self, op: Opcode, operands: &[usize]) -> Option<String> {
+        match (op, operands) {
+            (Opcode::Constant | Opcode::Closure, [index, ..]) => {
+                self.constants.get(*index).map(|x| x.to_string())
+            }
+            (Opcode::GetBuiltin, [index]) => {
+                BuiltInFn::ALL.get(*index).map(|x| x.name().to_string())
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Display for Constant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Constant::Int(int) => write!(f, "{}", int),
+            Constant::String(string) => write!(f, "{:?}", string),
+            //compiled files only know how many parameters a function has
+            Constant::Function(function) => match &function.source {
+                Some(source) => {
+                    let params = source
+                        .parameters
+                        .iter()
+                        .map(|x| x.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    write!(f, "fn({})", params)
+                }
+                None => write!(f, "fn/{}", function.num_parameters),
+            },
+        }
+    }
+}
+
+impl Display for Bytecode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.instructions
+            .disassemble(f, "", |op, operands| self.annotate(op, operands))?;
+        for (index, constant) in self.constants.iter().enumerate() {
+            let Constant::Function(function) = constant else {
+                continue;
+            };
+            writeln!(
+                f,
+                "\nconstant {}: {} locals={}",
+                index, constant, function.num_locals
+            )?;
+            function
+                .instructions
+                .disassemble(f, "  ", |op, operands| self.annotate(op, operands))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        compiler::{CompiledFunction, Compiler},
+        lexer::Lexer,
+        parser::Parser,
+        vm::Vm,
+    };
+    use insta::assert_snapshot;
+    use std::rc::Rc;
+
+    fn compile(input: &str) -> Bytecode {
+        let lexer = Lexer::new(input);
+        let parser = Parser::new(lexer);
+        Compiler::new().compile(parser).unwrap()
+    }
+
+    #[test]
+    fn disassemble_program() {
+        let input = r#"
+            let greeting = "hello";
+            let newAdder = fn(x) { fn(y) { x + y } };
+            let addTwo = newAdder(2);
+            if (len(greeting) > 3) { addTwo(1) } else { nil }
+        "#;
+
+        assert_snapshot!(compile(input).to_string());
+    }
+
+    #[test]
+    fn bytecode_file_roundtrip() {
+        let input = "
+            let fib = fn(x) { if (x <= 2) { 1 } else { fib(x - 1) + fib(x - 2) } };
+            [fib(10), \"done\"]
+        ";
+        let bytecode = compile(input);
+
+        let loaded = Bytecode::from_bytes(&bytecode.to_bytes().unwrap()).unwrap();
+        assert_eq!(loaded.instructions, bytecode.instructions);
+        assert_eq!(loaded.constants, bytecode.constants);
+        assert_eq!(Vm::new().run(loaded).unwrap().to_string(), r#"[55, done]"#);
+    }
+
+    #[test]
+    fn bytecode_files_leave_out_the_source() {
+        let input = "let secret = fn(hidden) { let local = hidden * 2; local }; secret";
+        let bytes = compile(input).to_bytes().unwrap();
+        for name in ["secret", "hidden", "local"] {
+            let found = bytes.windows(name.len()).any(|x| x == name.as_bytes());
+            assert!(!found, "{} is in the compiled file", name);
+        }
+
+        let loaded = Bytecode::from_bytes(&bytes).unwrap();
+        assert!(loaded.to_string().contains("constant 1: fn/1 locals=2"));
+        assert_eq!(Vm::new().run(loaded).unwrap().to_string(), "fn/1");
+    }
+
+    #[test]
+    fn reject_invalid_bytecode_files() {
+        let bytes = compile("1 + 2").to_bytes().unwrap();
+
+        let mut wrong_version = bytes.clone();
+        wrong_version[4] = 0xff;
+        let mut instructions = Instructions::new();
+        instructions.push(Opcode::Constant, &[5]);
+        let bad_constant = Bytecode {
+            instructions,
+            constants: vec![],
+        }
+        .to_bytes()
+        .unwrap();
+
+        let errors = [
+            b"#!/bin/monkey".as_slice(),
+            &wrong_version,
+            &bytes[..bytes.len() - 3],
+            &bad_constant,
+        ]
+        .iter()
+        .map(|x| Bytecode::from_bytes(x).unwrap_err().to_string())
+        .collect::<Vec<_>>();
+
+        assert_eq!(
+            errors,
+            vec![
+                "not a compiled monkey file",
+                "unsupported bytecode version 255, expected 2",
+                "corrupted bytecode file",
+                "invalid constant 5 at 0000",
+            ]
+        );
+    }
+
+    #[test]
+    fn reject_malformed_instructions() {
+        let program = |code: &[(Opcode, &[usize])], function: &[(Opcode, &[usize])]| {
+            let assemble = |code: &[(Opcode, &[usize])]| {
+                let mut instructions = Instructions::new();
+                code.iter().for_each(|(op, operands)| {
+                    instructions.push(*op, operands);
+                });
+                instructions
+            };
+            let function = CompiledFunction {
+                instructions: assemble(function),
+                num_locals: 1,
+                num_parameters: 0,
+                source: None,
+            };
+            Bytecode {
+                instructions: assemble(code),
+                constants: vec![Constant::Int(1), Constant::Function(Rc::new(function))],
+            }
+        };
+        let closure = [(Opcode::Closure, [1, 0].as_slice()), (Opcode::Call, &[0])];
+        let counts = |num_locals, num_parameters| {
+            let mut bytecode = program(&closure, &[]);
+            if let Constant::Function(function) = &mut bytecode.constants[1] {
+                let function = Rc::make_mut(function);
+                function.num_locals = num_locals;
+                function.num_parameters = num_parameters;
+            }
+            bytecode
+        };
+        let malformed = [
+            program(&[(Opcode::GetLocal, &[200])], &[]),
+            program(&closure, &[(Opcode::SetLocal, &[1])]),
+            program(&closure, &[(Opcode::GetFree, &[0])]),
+            program(&[(Opcode::GetFree, &[0])], &[]),
+            program(&[(Opcode::Jump, &[1]), (Opcode::Constant, &[0])], &[]),
+            counts(usize::MAX, 0),
+            counts(1, 2),
+        ];
+
+        let errors: Vec<_> = malformed
+            .iter()
+            .map(|x| Bytecode::from_bytes(&x.to_bytes().unwrap()).unwrap_err())
+            .map(|x| x.to_string())
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                "invalid local 200 at 0000",
+                "invalid local 1 at 0000",
+                "function constant 1 reads 1 free variables but only captures 0",
+                "free variable outside of a function",
+                "invalid jump target 0001 at 0000",
+                "function constant 1 has 18446744073709551615 locals, max is 256",
+                "function constant 1 has 2 parameters but 1 locals",
+            ]
+        );
+
+        //the stack isn't verified, and the vm doesn't rely on the checks above either
+        let lone_add = program(&[(Opcode::Add, &[])], &[]);
+        let lone_add = Bytecode::from_bytes(&lone_add.to_bytes().unwrap()).unwrap();
+        for bytecode in malformed.into_iter().chain([lone_add]) {
+            assert!(Vm::new().run(bytecode).is_err());
+        }
+    }
+}
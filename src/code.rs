@@ -0,0 +1,324 @@
+use std::{
+    fmt::{self, Display},
+    ops::{Deref, DerefMut},
+};
+
+use anyhow::{anyhow, ensure, Result};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum Opcode {
+    Constant,
+    Pop,
+    True,
+    False,
+    Nil,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Eq,
+    NotEq,
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+    Minus,
+    Bang,
+    Jump,
+    JumpNotTruthy,
+    GetGlobal,
+    SetGlobal,
+    GetLocal,
+    SetLocal,
+    GetBuiltin,
+    GetFree,
+    CurrentClosure,
+    Array,
+    Hash,
+    Index,
+    Call,
+    ReturnValue,
+    Closure,
+}
+
+impl Opcode {
+    /// Every opcode, indexed by its byte value.
+    pub const ALL: [Opcode; 32] = [
+        Opcode::Constant,
+        Opcode::Pop,
+        Opcode::True,
+        Opcode::False,
+        Opcode::Nil,
+        Opcode::Add,
+        Opcode::Sub,
+        Opcode::Mul,
+        Opcode::Div,
+        Opcode::Eq,
+        Opcode::NotEq,
+        Opcode::Lt,
+        Opcode::Lte,
+        Opcode::Gt,
+        Opcode::Gte,
+        Opcode::Minus,
+        Opcode::Bang,
+        Opcode::Jump,
+        Opcode::JumpNotTruthy,
+        Opcode::GetGlobal,
+        Opcode::SetGlobal,
+        Opcode::GetLocal,
+        Opcode::SetLocal,
+        Opcode::GetBuiltin,
+        Opcode::GetFree,
+        Opcode::CurrentClosure,
+        Opcode::Array,
+        Opcode::Hash,
+        Opcode::Index,
+        Opcode::Call,
+        Opcode::ReturnValue,
+        Opcode::Closure,
+    ];
+
+    /// Size in bytes of each operand following the opcode.
+    pub const fn operand_widths(&self) -> &'static [usize] {
+        match self {
+            Opcode::Constant
+            | Opcode::Jump
+            | Opcode::JumpNotTruthy
+            | Opcode::GetGlobal
+            | Opcode::SetGlobal
+            | Opcode::Array
+            | Opcode::Hash => &[2],
+            Opcode::GetLocal
+            | Opcode::SetLocal
+            | Opcode::GetBuiltin
+            | Opcode::GetFree
+            | Opcode::Call => &[1],
+            Opcode::Closure => &[2, 1],
+            Opcode::Pop
+            | Opcode::True
+            | Opcode::False
+            | Opcode::Nil
+            | Opcode::Add
+            | Opcode::Sub
+            | Opcode::Mul
+            | Opcode::Div
+            | Opcode::Eq
+            | Opcode::NotEq
+            | Opcode::Lt
+            | Opcode::Lte
+            | Opcode::Gt
+            | Opcode::Gte
+            | Opcode::Minus
+            | Opcode::Bang
+            | Opcode::CurrentClosure
+            | Opcode::Index
+            | Opcode::ReturnValue => &[],
+        }
+    }
+
+    /// Total size in bytes of the instruction, opcode included.
+    pub fn width(&self) -> usize {
+        1 + self.operand_widths().iter().sum::<usize>()
+    }
+}
+
+impl TryFrom<u8> for Opcode {
+    type Error = anyhow::Error;
+
+    #[inline]
+    fn try_from(byte: u8) -> Result<Self, Self::Error> {
+        Opcode::ALL
+            .get(byte as usize)
+            .copied()
+            .ok_or(anyhow!("Unknown opcode {}", byte))
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Instructions(pub Vec<u8>);
+
+impl Instructions {
+    pub fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    /// Encodes `op` with its big-endian `operands`, returning the offset it was written at.
+    /// Each operand must fit in its width, the compiler checks them before emitting.
+    pub fn push(&mut self, op: Opcode, operands: &[usize]) -> usize {
+        let position = self.0.len();
+        self.0.push(op as u8);
+        for (width, operand) in op.operand_widths().iter().zip(operands) {
+            debug_assert!(*operand < 1 << (8 * width), "operand {} too large", operand);
+            match width {
+                2 => self.0.extend_from_slice(&(*operand as u16).to_be_bytes()),
+                1 => self.0.push(*operand as u8),
+                _ => unreachable!("unsupported operand width {}", width),
+            }
+        }
+        position
+    }
+
+    /// Overwrites the first operand of the instruction at `position`.
+    pub fn patch_operand(&mut self, position: usize, operand: usize) {
+        let op = Opcode::try_from(self.0[position]).expect("patching a valid instruction");
+        match op.operand_widths().first() {
+            Some(2) => {
+                self.0[position + 1..position + 3].copy_from_slice(&(operand as u16).to_be_bytes())
+            }
+            Some(1) => self.0[position + 1] = operand as u8,
+            _ => unreachable!("{:?} has no operand to patch", op),
+        }
+    }
+
+    /// Decodes the instruction at `offset` into its opcode and operands.
+    pub fn decode(&self, offset: usize) -> Result<(Opcode, Vec<usize>)> {
+        let op = Opcode::try_from(self.0[offset])?;
+        ensure!(
+            offset + op.width() <= self.0.len(),
+            "truncated {:?} instruction at {:04}",
+            op,
+            offset
+        );
+        let mut position = offset + 1;
+        let operands = op
+            .operand_widths()
+            .iter()
+            .map(|width| {
+                let operand = match width {
+                    2 => self.read_u16(position) as usize,
+                    _ => self.read_u8(position) as usize,
+                };
+                position += width;
+                operand
+            })
+            .collect();
+        Ok((op, operands))
+    }
+
+    /// Writes one line per instruction, letting `annotate` append extra context to each of them.
+    pub fn disassemble(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        indent: &str,
+        annotate: impl Fn(Opcode, &[usize]) -> Option<String>,
+    ) -> fmt::Result {
+        let mut offset = 0;
+        while offset < self.0.len() {
+            let (op, operands) = match self.decode(offset) {
+                Ok(ok) => ok,
+                Err(err) => return writeln!(f, "{}{:04} <{}>", indent, offset, err),
+            };
+            write!(f, "{}{:04} {:?}", indent, offset, op)?;
+            for operand in &operands {
+                write!(f, " {}", operand)?;
+            }
+            match annotate(op, &operands) {
+                Some(annotation) => writeln!(f, " ({})", annotation)?,
+                None => writeln!(f)?,
+            }
+            offset += op.width();
+        }
+        Ok(())
+    }
+
+    #[inline]
+    pub fn read_u16(&self, offset: usize) -> u16 {
+        u16::from_be_bytes([self.0[offset], self.0[offset + 1]])
+    }
+
+    #[inline]
+    pub fn read_u8(&self, offset: usize) -> u8 {
+        self.0[offset]
+    }
+}
+
+impl Display for Instructions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.disassemble(f, "", |_, _| None)
+    }
+}
+
+impl Deref for Instructions {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for Instructions {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl From<Vec<Instructions>> for Instructions {
+    fn from(parts: Vec<Instructions>) -> Self {
+        Self(parts.into_iter().flat_map(|x| x.0).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opcode_table_matches_discriminants() {
+        for (byte, op) in Opcode::ALL.iter().enumerate() {
+            assert_eq!(*op as u8 as usize, byte);
+            assert_eq!(Opcode::try_from(byte as u8).unwrap(), *op);
+        }
+        assert!(Opcode::try_from(Opcode::ALL.len() as u8).is_err());
+    }
+
+    #[test]
+    fn encode_operands() {
+        let mut instructions = Instructions::new();
+        instructions.push(Opcode::Constant, &[65534]);
+        instructions.push(Opcode::GetLocal, &[255]);
+        instructions.push(Opcode::Closure, &[65534, 255]);
+        instructions.push(Opcode::Add, &[]);
+
+        assert_eq!(
+            instructions.0,
+            vec![
+                Opcode::Constant as u8,
+                255,
+                254,
+                Opcode::GetLocal as u8,
+                255,
+                Opcode::Closure as u8,
+                255,
+                254,
+                255,
+                Opcode::Add as u8
+            ]
+        );
+    }
+
+    #[test]
+    fn disassemble_instructions() {
+        let mut instructions = Instructions::new();
+        instructions.push(Opcode::Add, &[]);
+        instructions.push(Opcode::GetLocal, &[1]);
+        instructions.push(Opcode::Constant, &[2]);
+        instructions.push(Opcode::Constant, &[65535]);
+        instructions.push(Opcode::Closure, &[65535, 255]);
+        instructions.push(Opcode::Jump, &[0]);
+        instructions.0.push(Opcode::Constant as u8);
+
+        assert_eq!(
+            instructions.to_string(),
+            "0000 Add
+0001 GetLocal 1
+0003 Constant 2
+0006 Constant 65535
+0009 Closure 65535 255
+0013 Jump 0
+0016 <truncated Constant instruction at 0016>
+"
+        );
+    }
+}
@@ -0,0 +1,803 @@
+use std::{mem, rc::Rc};
+
+use anyhow::{anyhow, ensure, Result};
+use fnv::{FnvHashMap, FnvHashSet};
+use serde::{Deserialize, Serialize};
+use smol_str::SmolStr;
+
+use crate::ast::{
+    BinaryOperator, Expression, FunctionExpression, Literal, Statement, UnaryOperator,
+};
+use crate::code::{Instructions, Opcode};
+use crate::object::{BuiltInFn, EvalError};
+use crate::parser::Parser;
+use crate::token::Identifier;
+use crate::visitor::{walk_statement, Visitor};
+use crate::vm::STACK_SIZE;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolScope {
+    Global,
+    Local,
+    Builtin,
+    Free,
+    Function,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Symbol {
+    pub scope: SymbolScope,
+    pub index: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct SymbolTable {
+    store: FnvHashMap<Identifier, Symbol>,
+    //globals declared ahead of their `let`, so functions can refer to the ones defined later
+    hoisted: FnvHashSet<Identifier>,
+    num_definitions: usize,
+    free_symbols: Vec<Symbol>,
+    outer: Option<Box<SymbolTable>>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn new_enclosed(outer: SymbolTable) -> Self {
+        Self {
+            outer: Some(Box::new(outer)),
+            ..Self::default()
+        }
+    }
+
+    pub fn define(&mut self, ident: &Identifier) -> Result<Symbol> {
+        let scope = match self.outer {
+            Some(_) => SymbolScope::Local,
+            None => SymbolScope::Global,
+        };
+        if self.hoisted.remove(ident) {
+            return Ok(self.store[ident]);
+        }
+        //parameters and `let`s share the same scope, like the tree-walking environment
+        if let Some(Symbol {
+            scope: SymbolScope::Global | SymbolScope::Local,
+            ..
+        }) = self.store.get(ident)
+        {
+            Err(EvalError::IdentifierAlreadyDefined(*ident))?
+        }
+        let (kind, max) = match scope {
+            SymbolScope::Global => ("global", u16::MAX as usize),
+            _ => ("local", u8::MAX as usize),
+        };
+        ensure!(
+            self.num_definitions <= max,
+            "too many {} bindings, max is {}",
+            kind,
+            max + 1
+        );
+        let symbol = Symbol {
+            scope,
+            index: self.num_definitions,
+        };
+        self.store.insert(*ident, symbol);
+        self.num_definitions += 1;
+        Ok(symbol)
+    }
+
+    /// Reserves a global slot for `ident` before its `let` runs, unless it already has one.
+    pub fn hoist(&mut self, ident: &Identifier) -> Result<()> {
+        if self.store.contains_key(ident) {
+            return Ok(());
+        }
+        self.define(ident)?;
+        self.hoisted.insert(*ident);
+        Ok(())
+    }
+
+    pub fn define_function_name(&mut self, ident: &Identifier) -> Symbol {
+        let symbol = Symbol {
+            scope: SymbolScope::Function,
+            index: 0,
+        };
+        self.store.insert(*ident, symbol);
+        symbol
+    }
+
+    pub fn resolve(&mut self, ident: &Identifier) -> Option<Symbol> {
+        self.lookup(ident, false)
+    }
+
+    //a hoisted global is visible to functions, but not to the statements before its `let`
+    fn lookup(&mut self, ident: &Identifier, nested: bool) -> Option<Symbol> {
+        if let Some(symbol) = self.store.get(ident) {
+            if nested || !self.hoisted.contains(ident) {
+                return Some(*symbol);
+            }
+        }
+        let Some(outer) = self.outer.as_mut() else {
+            return BuiltInFn::from_name(ident.as_str()).map(|builtin| Symbol {
+                scope: SymbolScope::Builtin,
+                index: builtin as usize,
+            });
+        };
+        match outer.lookup(ident, true)? {
+            symbol @ Symbol {
+                scope: SymbolScope::Global | SymbolScope::Builtin,
+                ..
+            } => Some(symbol),
+            symbol => Some(self.define_free(ident, symbol)),
+        }
+    }
+
+    fn define_free(&mut self, ident: &Identifier, original: Symbol) -> Symbol {
+        self.free_symbols.push(original);
+        let symbol = Symbol {
+            scope: SymbolScope::Free,
+            index: self.free_symbols.len() - 1,
+        };
+        self.store.insert(*ident, symbol);
+        symbol
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompiledFunction {
+    pub instructions: Instructions,
+    pub num_locals: usize,
+    pub num_parameters: usize,
+    //kept in memory so closures can be displayed and serialized like tree-walking functions,
+    //but never written to `.mkc` files, which ship without the source
+    #[serde(skip)]
+    pub source: Option<FunctionExpression>,
+}
+
+//the source only matters for display, the same code compiled twice is the same function
+impl PartialEq for CompiledFunction {
+    fn eq(&self, other: &Self) -> bool {
+        self.instructions == other.instructions
+            && self.num_locals == other.num_locals
+            && self.num_parameters == other.num_parameters
+    }
+}
+
+impl Eq for CompiledFunction {}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Constant {
+    Int(i64),
+    String(SmolStr),
+    Function(Rc<CompiledFunction>),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bytecode {
+    pub instructions: Instructions,
+    pub constants: Vec<Constant>,
+}
+
+#[derive(Debug, Default)]
+struct CompilationScope {
+    instructions: Instructions,
+}
+
+#[derive(Debug, Default)]
+pub struct Compiler {
+    constants: Vec<Constant>,
+    symbol_table: SymbolTable,
+    scopes: Vec<CompilationScope>,
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Declares a global the host sets before running the program, returning its index in the
+    /// vm's globals.
+    pub fn declare_global(&mut self, name: &str) -> Result<usize> {
+        Ok(self.symbol_table.define(&Identifier::new(name))?.index)
+    }
+
+    /// Compiles the parsed program, keeping globals and constants around for the next call.
+    pub fn compile(&mut self, parser: Parser) -> Result<Bytecode> {
+        self.scopes = vec![CompilationScope::default()];
+        let mut lets = GlobalLets(Vec::new());
+        lets.visit_program(&parser.nodes);
+        for ident in &lets.0 {
+            self.symbol_table.hoist(ident)?;
+        }
+        self.compile_block(&parser.nodes)?;
+        let main = self.scopes.pop().expect("main scope");
+        Ok(Bytecode {
+            instructions: main.instructions,
+            constants: self.constants.clone(),
+        })
+    }
+
+    //every block leaves exactly one value on the stack, just like `BlockStatement::eval`
+    fn compile_block(&mut self, statements: &[Statement]) -> Result<()> {
+        let Some((last, rest)) = statements.split_last() else {
+            self.emit(Opcode::Nil, &[])?;
+            return Ok(());
+        };
+        for statement in rest {
+            self.compile_statement(statement)?;
+            if let Statement::Expression(_) = statement {
+                self.emit(Opcode::Pop, &[])?;
+            }
+        }
+        self.compile_statement(last)?;
+        if let Statement::Let { .. } = last {
+            self.emit(Opcode::Nil, &[])?;
+        }
+        Ok(())
+    }
+
+    fn compile_statement(&mut self, statement: &Statement) -> Result<()> {
+        match statement {
+            Statement::Let {
+                identifier, value, ..
+            } => {
+                match value.as_ref() {
+                    Expression::Function(function) => {
+                        self.compile_function(function, Some(identifier))?
+                    }
+                    value => self.compile_expression(value)?,
+                }
+                let symbol = self.symbol_table.define(identifier)?;
+                match symbol.scope {
+                    SymbolScope::Global => self.emit(Opcode::SetGlobal, &[symbol.index])?,
+                    _ => self.emit(Opcode::SetLocal, &[symbol.index])?,
+                };
+            }
+            Statement::Return(exp) => {
+                self.compile_expression(exp)?;
+                self.emit(Opcode::ReturnValue, &[])?;
+            }
+            Statement::Expression(exp) => self.compile_expression(exp)?,
+        }
+        Ok(())
+    }
+
+    fn compile_expression(&mut self, expression: &Expression) -> Result<()> {
+        match expression {
+            Expression::Literal(literal) => self.compile_literal(literal)?,
+            Expression::Identifier(exp) => {
+                let symbol = self
+                    .symbol_table
+                    .resolve(&exp.ident)
+                    .ok_or(anyhow!("Identifier {} not found", exp.ident))?;
+                self.load_symbol(symbol)?;
+            }
+            Expression::UnaryExpression(exp) => {
+                self.compile_expression(&exp.value)?;
+                match exp.operator {
+                    UnaryOperator::Not => self.emit(Opcode::Bang, &[])?,
+                    UnaryOperator::Minus => self.emit(Opcode::Minus, &[])?,
+                };
+            }
+            Expression::BinaryExp(exp) => {
+                self.compile_expression(&exp.lhs)?;
+                self.compile_expression(&exp.rhs)?;
+                let op = match exp.operator {
+                    BinaryOperator::Eq => Opcode::Eq,
+                    BinaryOperator::NotEq => Opcode::NotEq,
+                    BinaryOperator::Lt => Opcode::Lt,
+                    BinaryOperator::Lte => Opcode::Lte,
+                    BinaryOperator::Gt => Opcode::Gt,
+                    BinaryOperator::Gte => Opcode::Gte,
+                    BinaryOperator::Add => Opcode::Add,
+                    BinaryOperator::Sub => Opcode::Sub,
+                    BinaryOperator::Mul => Opcode::Mul,
+                    BinaryOperator::Div => Opcode::Div,
+                };
+                self.emit(op, &[])?;
+            }
+            Expression::If(exp) => {
+                self.compile_expression(&exp.condition)?;
+                let jump_not_truthy = self.emit(Opcode::JumpNotTruthy, &[u16::MAX as usize])?;
+                self.compile_block(&exp.consequence.0)?;
+                let jump = self.emit(Opcode::Jump, &[u16::MAX as usize])?;
+                self.patch_jump(jump_not_truthy)?;
+                match &exp.alternative {
+                    Some(alternative) => self.compile_block(&alternative.0)?,
+                    None => {
+                        self.emit(Opcode::Nil, &[])?;
+                    }
+                }
+                self.patch_jump(jump)?;
+            }
+            Expression::IndexExpression(exp) => {
+                self.compile_expression(&exp.container)?;
+                self.compile_expression(&exp.index)?;
+                self.emit(Opcode::Index, &[])?;
+            }
+            Expression::Function(function) => self.compile_function(function, None)?,
+            Expression::Call(exp) => {
+                self.compile_expression(&exp.function)?;
+                ensure!(
+                    exp.arguments.len() <= u8::MAX as usize,
+                    "too many arguments, max is {}",
+                    u8::MAX
+                );
+                for argument in &exp.arguments {
+                    self.compile_expression(argument)?;
+                }
+                self.emit(Opcode::Call, &[exp.arguments.len()])?;
+            }
+        };
+        Ok(())
+    }
+
+    fn compile_literal(&mut self, literal: &Literal) -> Result<()> {
+        match literal {
+            Literal::Int(int) => {
+                self.emit_constant(Constant::Int(*int))?;
+            }
+            Literal::String(string) => {
+                self.emit_constant(Constant::String(string.clone()))?;
+            }
+            Literal::True => {
+                self.emit(Opcode::True, &[])?;
+            }
+            Literal::False => {
+                self.emit(Opcode::False, &[])?;
+            }
+            Literal::Nil => {
+                self.emit(Opcode::Nil, &[])?;
+            }
+            Literal::Array(array) => {
+                ensure!(
+                    array.len() <= STACK_SIZE,
+                    "array literal too large, max is {} elements",
+                    STACK_SIZE
+                );
+                for element in array {
+                    self.compile_expression(element)?;
+                }
+                self.emit(Opcode::Array, &[array.len()])?;
+            }
+            Literal::Hash(hash) => {
+                ensure!(
+                    hash.len() <= STACK_SIZE / 2,
+                    "hash literal too large, max is {} entries",
+                    STACK_SIZE / 2
+                );
+                for (key, value) in hash {
+                    self.compile_expression(key)?;
+                    self.compile_expression(value)?;
+                }
+                self.emit(Opcode::Hash, &[hash.len() * 2])?;
+            }
+        }
+        Ok(())
+    }
+
+    fn compile_function(
+        &mut self,
+        function: &FunctionExpression,
+        name: Option<&Identifier>,
+    ) -> Result<()> {
+        self.enter_scope();
+        if let Some(name) = name {
+            self.symbol_table.define_function_name(name);
+        }
+        let compiled = function
+            .parameters
+            .iter()
+            .try_for_each(|param| self.symbol_table.define(param).map(|_| ()))
+            .and_then(|_| self.compile_block(&function.body.0))
+            .and_then(|_| self.emit(Opcode::ReturnValue, &[]));
+        let num_locals = self.symbol_table.num_definitions;
+        let (instructions, free_symbols) = self.leave_scope();
+        compiled?;
+
+        for symbol in &free_symbols {
+            self.load_symbol(*symbol)?;
+        }
+        let index = self.add_constant(Constant::Function(Rc::new(CompiledFunction {
+            instructions,
+            num_locals,
+            num_parameters: function.parameters.len(),
+            source: Some(function.clone()),
+        })))?;
+        self.emit(Opcode::Closure, &[index, free_symbols.len()])?;
+        Ok(())
+    }
+
+    fn load_symbol(&mut self, symbol: Symbol) -> Result<usize> {
+        match symbol.scope {
+            SymbolScope::Global => self.emit(Opcode::GetGlobal, &[symbol.index]),
+            SymbolScope::Local => self.emit(Opcode::GetLocal, &[symbol.index]),
+            SymbolScope::Builtin => self.emit(Opcode::GetBuiltin, &[symbol.index]),
+            SymbolScope::Free => self.emit(Opcode::GetFree, &[symbol.index]),
+            SymbolScope::Function => self.emit(Opcode::CurrentClosure, &[]),
+        }
+    }
+
+    fn enter_scope(&mut self) {
+        self.scopes.push(CompilationScope::default());
+        self.symbol_table = SymbolTable::new_enclosed(mem::take(&mut self.symbol_table));
+    }
+
+    fn leave_scope(&mut self) -> (Instructions, Vec<Symbol>) {
+        let scope = self.scopes.pop().expect("function scope");
+        let table = mem::take(&mut self.symbol_table);
+        self.symbol_table = *table.outer.expect("enclosed symbol table");
+        (scope.instructions, table.free_symbols)
+    }
+
+    fn add_constant(&mut self, constant: Constant) -> Result<usize> {
+        ensure!(
+            self.constants.len() <= u16::MAX as usize,
+            "too many constants, max is {}",
+            u16::MAX as usize + 1
+        );
+        self.constants.push(constant);
+        Ok(self.constants.len() - 1)
+    }
+
+    fn emit_constant(&mut self, constant: Constant) -> Result<usize> {
+        let index = self.add_constant(constant)?;
+        self.emit(Opcode::Constant, &[index])
+    }
+
+    fn emit(&mut self, op: Opcode, operands: &[usize]) -> Result<usize> {
+        for (width, operand) in op.operand_widths().iter().zip(operands) {
+            ensure!(
+                *operand < 1 << (8 * width),
+                "operand {} of {:?} doesn't fit in {} bytes",
+                operand,
+                op,
+                width
+            );
+        }
+        Ok(self.current_instructions().push(op, operands))
+    }
+
+    fn patch_jump(&mut self, position: usize) -> Result<()> {
+        let target = self.current_instructions().len();
+        ensure!(
+            target <= u16::MAX as usize,
+            "function body too large to jump over"
+        );
+        self.current_instructions().patch_operand(position, target);
+        Ok(())
+    }
+
+    fn current_instructions(&mut self) -> &mut Instructions {
+        &mut self
+            .scopes
+            .last_mut()
+            .expect("compilation scope")
+            .instructions
+    }
+}
+
+//names of the `let`s in the global scope, `if` blocks included but not function bodies
+struct GlobalLets(Vec<Identifier>);
+
+impl Visitor for GlobalLets {
+    fn visit_statement(&mut self, statement: &Statement) {
+        if let Statement::Let { identifier, .. } = statement {
+            self.0.push(*identifier);
+        }
+        walk_statement(self, statement);
+    }
+
+    fn visit_function(&mut self, _: &FunctionExpression) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use pretty_assertions::assert_eq;
+
+    #[derive(Debug, PartialEq)]
+    enum Expected {
+        Int(i64),
+        String(&'static str),
+        Function(Instructions),
+    }
+
+    fn make(instructions: &[(Opcode, &[usize])]) -> Instructions {
+        let mut result = Instructions::new();
+        for (op, operands) in instructions {
+            result.push(*op, operands);
+        }
+        result
+    }
+
+    fn compile(input: &str) -> Result<Bytecode> {
+        let lexer = Lexer::new(input);
+        let parser = Parser::new(lexer);
+        Compiler::new().compile(parser)
+    }
+
+    fn assert_compiles(input: &str, constants: Vec<Expected>, instructions: Instructions) {
+        let bytecode = compile(input).unwrap();
+        let actual = bytecode
+            .constants
+            .into_iter()
+            .map(|constant| match constant {
+                Constant::Int(int) => Expected::Int(int),
+                Constant::String(string) => Expected::String(Box::leak(string.as_str().into())),
+                Constant::Function(function) => Expected::Function(function.instructions.clone()),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(actual, constants, "constants of {}", input);
+        assert_eq!(
+            bytecode.instructions, instructions,
+            "instructions of {}",
+            input
+        );
+    }
+
+    #[test]
+    fn compile_integer_arithmetic() {
+        assert_compiles(
+            "1 + 2; -3",
+            vec![Expected::Int(1), Expected::Int(2), Expected::Int(3)],
+            make(&[
+                (Opcode::Constant, &[0]),
+                (Opcode::Constant, &[1]),
+                (Opcode::Add, &[]),
+                (Opcode::Pop, &[]),
+                (Opcode::Constant, &[2]),
+                (Opcode::Minus, &[]),
+            ]),
+        );
+        assert_compiles(
+            "!(1 <= 2) == false",
+            vec![Expected::Int(1), Expected::Int(2)],
+            make(&[
+                (Opcode::Constant, &[0]),
+                (Opcode::Constant, &[1]),
+                (Opcode::Lte, &[]),
+                (Opcode::Bang, &[]),
+                (Opcode::False, &[]),
+                (Opcode::Eq, &[]),
+            ]),
+        );
+    }
+
+    #[test]
+    fn compile_conditionals() {
+        assert_compiles(
+            "if (true) { 10 }; 3333;",
+            vec![Expected::Int(10), Expected::Int(3333)],
+            make(&[
+                (Opcode::True, &[]),
+                (Opcode::JumpNotTruthy, &[10]),
+                (Opcode::Constant, &[0]),
+                (Opcode::Jump, &[11]),
+                (Opcode::Nil, &[]),
+                (Opcode::Pop, &[]),
+                (Opcode::Constant, &[1]),
+            ]),
+        );
+        assert_compiles(
+            "if (true) { 10 } else { let a = 20; }",
+            vec![Expected::Int(10), Expected::Int(20)],
+            make(&[
+                (Opcode::True, &[]),
+                (Opcode::JumpNotTruthy, &[10]),
+                (Opcode::Constant, &[0]),
+                (Opcode::Jump, &[17]),
+                (Opcode::Constant, &[1]),
+                (Opcode::SetGlobal, &[0]),
+                (Opcode::Nil, &[]),
+            ]),
+        );
+    }
+
+    #[test]
+    fn compile_global_let_statements() {
+        assert_compiles(
+            "let one = 1; let two = one; two;",
+            vec![Expected::Int(1)],
+            make(&[
+                (Opcode::Constant, &[0]),
+                (Opcode::SetGlobal, &[0]),
+                (Opcode::GetGlobal, &[0]),
+                (Opcode::SetGlobal, &[1]),
+                (Opcode::GetGlobal, &[1]),
+            ]),
+        );
+        assert_compiles(
+            "let one = 1;",
+            vec![Expected::Int(1)],
+            make(&[
+                (Opcode::Constant, &[0]),
+                (Opcode::SetGlobal, &[0]),
+                (Opcode::Nil, &[]),
+            ]),
+        );
+    }
+
+    #[test]
+    fn compile_collection_literals() {
+        assert_compiles(
+            r#"[1, "two"][0]; {1: 2}"#,
+            vec![
+                Expected::Int(1),
+                Expected::String("two"),
+                Expected::Int(0),
+                Expected::Int(1),
+                Expected::Int(2),
+            ],
+            make(&[
+                (Opcode::Constant, &[0]),
+                (Opcode::Constant, &[1]),
+                (Opcode::Array, &[2]),
+                (Opcode::Constant, &[2]),
+                (Opcode::Index, &[]),
+                (Opcode::Pop, &[]),
+                (Opcode::Constant, &[3]),
+                (Opcode::Constant, &[4]),
+                (Opcode::Hash, &[2]),
+            ]),
+        );
+    }
+
+    #[test]
+    fn compile_functions_and_calls() {
+        assert_compiles(
+            "let add = fn(a, b) { let c = a + b; return c; }; add(1, len([]));",
+            vec![
+                Expected::Function(make(&[
+                    (Opcode::GetLocal, &[0]),
+                    (Opcode::GetLocal, &[1]),
+                    (Opcode::Add, &[]),
+                    (Opcode::SetLocal, &[2]),
+                    (Opcode::GetLocal, &[2]),
+                    (Opcode::ReturnValue, &[]),
+                    (Opcode::ReturnValue, &[]),
+                ])),
+                Expected::Int(1),
+            ],
+            make(&[
+                (Opcode::Closure, &[0, 0]),
+                (Opcode::SetGlobal, &[0]),
+                (Opcode::GetGlobal, &[0]),
+                (Opcode::Constant, &[1]),
+                (Opcode::GetBuiltin, &[BuiltInFn::Len as usize]),
+                (Opcode::Array, &[0]),
+                (Opcode::Call, &[1]),
+                (Opcode::Call, &[2]),
+            ]),
+        );
+        assert_compiles(
+            "fn() { }",
+            vec![Expected::Function(make(&[
+                (Opcode::Nil, &[]),
+                (Opcode::ReturnValue, &[]),
+            ]))],
+            make(&[(Opcode::Closure, &[0, 0])]),
+        );
+    }
+
+    #[test]
+    fn compile_closures() {
+        assert_compiles(
+            "fn(a) { fn(b) { fn(c) { a + b + c } } }",
+            vec![
+                Expected::Function(make(&[
+                    (Opcode::GetFree, &[0]),
+                    (Opcode::GetFree, &[1]),
+                    (Opcode::Add, &[]),
+                    (Opcode::GetLocal, &[0]),
+                    (Opcode::Add, &[]),
+                    (Opcode::ReturnValue, &[]),
+                ])),
+                Expected::Function(make(&[
+                    (Opcode::GetFree, &[0]),
+                    (Opcode::GetLocal, &[0]),
+                    (Opcode::Closure, &[0, 2]),
+                    (Opcode::ReturnValue, &[]),
+                ])),
+                Expected::Function(make(&[
+                    (Opcode::GetLocal, &[0]),
+                    (Opcode::Closure, &[1, 1]),
+                    (Opcode::ReturnValue, &[]),
+                ])),
+            ],
+            make(&[(Opcode::Closure, &[2, 0])]),
+        );
+    }
+
+    #[test]
+    fn compile_recursive_functions() {
+        assert_compiles(
+            "let wrapper = fn() { let countDown = fn(x) { countDown(x - 1); }; countDown(1); };",
+            vec![
+                Expected::Int(1),
+                Expected::Function(make(&[
+                    (Opcode::CurrentClosure, &[]),
+                    (Opcode::GetLocal, &[0]),
+                    (Opcode::Constant, &[0]),
+                    (Opcode::Sub, &[]),
+                    (Opcode::Call, &[1]),
+                    (Opcode::ReturnValue, &[]),
+                ])),
+                Expected::Int(1),
+                Expected::Function(make(&[
+                    (Opcode::Closure, &[1, 0]),
+                    (Opcode::SetLocal, &[0]),
+                    (Opcode::GetLocal, &[0]),
+                    (Opcode::Constant, &[2]),
+                    (Opcode::Call, &[1]),
+                    (Opcode::ReturnValue, &[]),
+                ])),
+            ],
+            make(&[
+                (Opcode::Closure, &[3, 0]),
+                (Opcode::SetGlobal, &[0]),
+                (Opcode::Nil, &[]),
+            ]),
+        );
+    }
+
+    #[test]
+    fn compile_errors() {
+        let input = [
+            "foobar;",
+            "let foo = 3; let foo = 4;",
+            "let a = a;",
+            "fn(x) { let x = 1; }",
+        ];
+
+        let result = input
+            .iter()
+            .map(|x| compile(x).unwrap_err().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            result,
+            vec![
+                "Identifier foobar not found",
+                "Identifier foo already defined",
+                "Identifier a not found",
+                "Identifier x already defined",
+            ]
+        );
+    }
+
+    #[test]
+    fn reject_operands_out_of_range() {
+        let names = |count: usize| (0..count).map(|x| format!("a{}", x)).collect::<Vec<_>>();
+        let lets = |names: &[String]| names.iter().map(|x| format!("let {} = nil;", x)).collect();
+        let nils = |count: usize, item: &str| vec![item; count].join(", ");
+
+        let globals: String = lets(&names(u16::MAX as usize + 2));
+        let locals = names(u8::MAX as usize + 1);
+        let captures = format!(
+            "fn() {{ {} fn() {{ [{}] }} }}",
+            lets(&locals),
+            locals.join(", ")
+        );
+        let input = [
+            globals,
+            format!("[{}]", nils(STACK_SIZE + 1, "nil")),
+            format!("{{{}}}", nils(STACK_SIZE / 2 + 1, "nil: nil")),
+            captures,
+        ];
+        let result = input
+            .iter()
+            .map(|x| compile(x).unwrap_err().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            result,
+            vec![
+                "too many global bindings, max is 65536",
+                "array literal too large, max is 2048 elements",
+                "hash literal too large, max is 1024 entries",
+                "operand 256 of Closure doesn't fit in 1 bytes",
+            ]
+        );
+        assert!(compile(&format!("[{}]", nils(STACK_SIZE, "nil"))).is_ok());
+    }
+}
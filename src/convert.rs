@@ -0,0 +1,828 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    hash::{BuildHasher, Hash},
+};
+
+use serde::{
+    de::{
+        self,
+        value::{MapDeserializer, SeqDeserializer},
+        DeserializeOwned, IntoDeserializer, Visitor,
+    },
+    forward_to_deserialize_any,
+    ser::{self, Serialize},
+};
+use smol_str::SmolStr;
+use thiserror::Error;
+
+use crate::object::{Array, HashTable, Object, NIL};
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ConvertError {
+    #[error("expected {expected}, found: {found}")]
+    Mismatch {
+        expected: &'static str,
+        found: String,
+    },
+    #[error("{0} doesn't fit in the target type")]
+    OutOfRange(String),
+    #[error("{0}")]
+    Custom(String),
+}
+
+impl ConvertError {
+    fn mismatch(expected: &'static str, found: &Object) -> Self {
+        Self::Mismatch {
+            expected,
+            found: found.to_string(),
+        }
+    }
+}
+
+impl ser::Error for ConvertError {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self::Custom(msg.to_string())
+    }
+}
+
+impl de::Error for ConvertError {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self::Custom(msg.to_string())
+    }
+}
+
+type Result<T> = std::result::Result<T, ConvertError>;
+
+/// Rust values that can be handed to a script.
+pub trait IntoObject {
+    fn into_object(self) -> Object;
+}
+
+/// Rust values that can be read back from a script value.
+pub trait FromObject: Sized {
+    fn from_object(object: Object) -> Result<Self>;
+}
+
+impl IntoObject for Object {
+    fn into_object(self) -> Object {
+        self
+    }
+}
+
+impl FromObject for Object {
+    fn from_object(object: Object) -> Result<Self> {
+        Ok(object)
+    }
+}
+
+impl IntoObject for () {
+    fn into_object(self) -> Object {
+        NIL
+    }
+}
+
+impl FromObject for () {
+    fn from_object(object: Object) -> Result<Self> {
+        match object {
+            Object::Nil => Ok(()),
+            other => Err(ConvertError::mismatch("nil", &other)),
+        }
+    }
+}
+
+impl IntoObject for bool {
+    fn into_object(self) -> Object {
+        Object::Bool(self)
+    }
+}
+
+impl FromObject for bool {
+    fn from_object(object: Object) -> Result<Self> {
+        match object {
+            Object::Bool(bool) => Ok(bool),
+            other => Err(ConvertError::mismatch("bool", &other)),
+        }
+    }
+}
+
+macro_rules! int_conversions {
+    ($($into:ty),* ; $($from:ty),*) => {
+        $(
+            impl IntoObject for $into {
+                fn into_object(self) -> Object {
+                    Object::Int(i64::from(self))
+                }
+            }
+        )*
+        $(
+            impl FromObject for $from {
+                fn from_object(object: Object) -> Result<Self> {
+                    match object {
+                        Object::Int(int) => int
+                            .try_into()
+                            .map_err(|_| ConvertError::OutOfRange(int.to_string())),
+                        other => Err(ConvertError::mismatch("int", &other)),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+//the unsigned 64 bits types don't always fit in an int, so they only come back from scripts
+int_conversions!(i8, i16, i32, i64, u8, u16, u32; i8, i16, i32, i64, u8, u16, u32, u64, usize);
+
+impl IntoObject for String {
+    fn into_object(self) -> Object {
+        Object::String(self.into())
+    }
+}
+
+impl IntoObject for &str {
+    fn into_object(self) -> Object {
+        Object::String(self.into())
+    }
+}
+
+impl IntoObject for SmolStr {
+    fn into_object(self) -> Object {
+        Object::String(self)
+    }
+}
+
+impl FromObject for SmolStr {
+    fn from_object(object: Object) -> Result<Self> {
+        match object {
+            Object::String(string) => Ok(string),
+            other => Err(ConvertError::mismatch("string", &other)),
+        }
+    }
+}
+
+impl FromObject for String {
+    fn from_object(object: Object) -> Result<Self> {
+        SmolStr::from_object(object).map(String::from)
+    }
+}
+
+impl<T: IntoObject> IntoObject for Option<T> {
+    fn into_object(self) -> Object {
+        self.map_or(NIL, T::into_object)
+    }
+}
+
+impl<T: FromObject> FromObject for Option<T> {
+    fn from_object(object: Object) -> Result<Self> {
+        match object {
+            Object::Nil => Ok(None),
+            other => T::from_object(other).map(Some),
+        }
+    }
+}
+
+impl<T: IntoObject> IntoObject for Vec<T> {
+    fn into_object(self) -> Object {
+        Object::Array(Array::new(self.into_iter().map(T::into_object).collect()))
+    }
+}
+
+impl<T: FromObject> FromObject for Vec<T> {
+    fn from_object(object: Object) -> Result<Self> {
+        match object {
+            Object::Array(array) => array.0.into_iter().map(T::from_object).collect(),
+            other => Err(ConvertError::mismatch("array", &other)),
+        }
+    }
+}
+
+impl<K: IntoObject, V: IntoObject, S> IntoObject for HashMap<K, V, S> {
+    fn into_object(self) -> Object {
+        let entries = self
+            .into_iter()
+            .map(|(key, value)| (key.into_object(), value.into_object()));
+        Object::HashTable(Box::new(entries.collect()))
+    }
+}
+
+impl<K, V, S> FromObject for HashMap<K, V, S>
+where
+    K: FromObject + Eq + Hash,
+    V: FromObject,
+    S: BuildHasher + Default,
+{
+    fn from_object(object: Object) -> Result<Self> {
+        match object {
+            Object::HashTable(hash) => hash
+                .0
+                .into_iter()
+                .map(|(key, value)| Ok((K::from_object(key.clone())?, V::from_object(value)?)))
+                .collect(),
+            other => Err(ConvertError::mismatch("hash", &other)),
+        }
+    }
+}
+
+//tuples are arrays of a fixed length
+macro_rules! tuple_conversions {
+    ($(($($name:ident),+)),*) => {
+        $(
+            impl<$($name: IntoObject),+> IntoObject for ($($name,)+) {
+                #[allow(non_snake_case)]
+                fn into_object(self) -> Object {
+                    let ($($name,)+) = self;
+                    Object::Array(Array::new(vec![$($name.into_object()),+]))
+                }
+            }
+
+            impl<$($name: FromObject),+> FromObject for ($($name,)+) {
+                fn from_object(object: Object) -> Result<Self> {
+                    let Object::Array(array) = object else {
+                        return Err(ConvertError::mismatch("array", &object));
+                    };
+                    if array.len() != [$(stringify!($name)),+].len() {
+                        return Err(ConvertError::Mismatch {
+                            expected: "an array as long as the tuple",
+                            found: Object::Array(array).to_string(),
+                        });
+                    }
+                    let mut items = array.0.into_iter();
+                    Ok(($($name::from_object(items.next().unwrap())?,)+))
+                }
+            }
+        )*
+    };
+}
+
+tuple_conversions!((A), (A, B), (A, B, C), (A, B, C, D));
+
+/// Turns any serializable value into a script value: structs and maps become hashes,
+/// sequences and tuples become arrays, and `None` and unit become `nil`.
+pub fn to_object<T: Serialize + ?Sized>(value: &T) -> Result<Object> {
+    value.serialize(Serializer)
+}
+
+/// The inverse of `to_object`.
+pub fn from_object<T: DeserializeOwned>(object: Object) -> Result<T> {
+    T::deserialize(Deserializer(object))
+}
+
+pub struct Serializer;
+
+//an enum variant with data is a hash with the variant name as its single key
+fn variant(name: &'static str, value: Object) -> Object {
+    Object::HashTable(Box::new(HashTable::from_iter([(
+        name.into_object(),
+        value,
+    )])))
+}
+
+impl ser::Serializer for Serializer {
+    type Ok = Object;
+    type Error = ConvertError;
+    type SerializeSeq = SerializeArray;
+    type SerializeTuple = SerializeArray;
+    type SerializeTupleStruct = SerializeArray;
+    type SerializeTupleVariant = SerializeArray;
+    type SerializeMap = SerializeHash;
+    type SerializeStruct = SerializeHash;
+    type SerializeStructVariant = SerializeHash;
+
+    fn serialize_bool(self, v: bool) -> Result<Object> {
+        Ok(v.into_object())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Object> {
+        Ok(v.into_object())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Object> {
+        Ok(v.into_object())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Object> {
+        Ok(v.into_object())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Object> {
+        Ok(v.into_object())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Object> {
+        Ok(v.into_object())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Object> {
+        Ok(v.into_object())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Object> {
+        Ok(v.into_object())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Object> {
+        i64::try_from(v)
+            .map(Object::Int)
+            .map_err(|_| ConvertError::OutOfRange(v.to_string()))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Object> {
+        self.serialize_f64(v.into())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Object> {
+        Err(ConvertError::Custom(format!(
+            "floats aren't supported, found: {}",
+            v
+        )))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Object> {
+        Ok(v.to_string().into_object())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Object> {
+        Ok(v.into_object())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Object> {
+        Ok(v.to_vec().into_object())
+    }
+
+    fn serialize_none(self) -> Result<Object> {
+        Ok(NIL)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Object> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Object> {
+        Ok(NIL)
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<Object> {
+        Ok(NIL)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Object> {
+        Ok(variant.into_object())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<Object> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Object> {
+        Ok(self::variant(variant, value.serialize(self)?))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeArray> {
+        Ok(SerializeArray {
+            items: Vec::with_capacity(len.unwrap_or_default()),
+            variant: None,
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SerializeArray> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, name: &'static str, len: usize) -> Result<SerializeArray> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SerializeArray> {
+        Ok(SerializeArray {
+            items: Vec::with_capacity(len),
+            variant: Some(variant),
+        })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<SerializeHash> {
+        Ok(SerializeHash {
+            entries: Vec::with_capacity(len.unwrap_or_default()),
+            key: None,
+            variant: None,
+        })
+    }
+
+    fn serialize_struct(self, name: &'static str, len: usize) -> Result<SerializeHash> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SerializeHash> {
+        Ok(SerializeHash {
+            entries: Vec::with_capacity(len),
+            key: None,
+            variant: Some(variant),
+        })
+    }
+}
+
+pub struct SerializeArray {
+    items: Vec<Object>,
+    variant: Option<&'static str>,
+}
+
+impl SerializeArray {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.items.push(to_object(value)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Object> {
+        let array = self.items.into_object();
+        Ok(match self.variant {
+            Some(name) => variant(name, array),
+            None => array,
+        })
+    }
+}
+
+impl ser::SerializeSeq for SerializeArray {
+    type Ok = Object;
+    type Error = ConvertError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Object> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SerializeArray {
+    type Ok = Object;
+    type Error = ConvertError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Object> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeArray {
+    type Ok = Object;
+    type Error = ConvertError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Object> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SerializeArray {
+    type Ok = Object;
+    type Error = ConvertError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Object> {
+        self.finish()
+    }
+}
+
+pub struct SerializeHash {
+    entries: Vec<(Object, Object)>,
+    //written by `serialize_key` and taken by the following `serialize_value`
+    key: Option<Object>,
+    variant: Option<&'static str>,
+}
+
+impl SerializeHash {
+    fn finish(self) -> Result<Object> {
+        let hash = Object::HashTable(Box::new(self.entries.into_iter().collect()));
+        Ok(match self.variant {
+            Some(name) => variant(name, hash),
+            None => hash,
+        })
+    }
+}
+
+impl ser::SerializeMap for SerializeHash {
+    type Ok = Object;
+    type Error = ConvertError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<()> {
+        self.key = Some(to_object(key)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| ConvertError::Custom("value serialized before its key".into()))?;
+        self.entries.push((key, to_object(value)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Object> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for SerializeHash {
+    type Ok = Object;
+    type Error = ConvertError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.entries.push((key.into_object(), to_object(value)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Object> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for SerializeHash {
+    type Ok = Object;
+    type Error = ConvertError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        ser::SerializeStruct::serialize_field(self, key, value)
+    }
+
+    fn end(self) -> Result<Object> {
+        self.finish()
+    }
+}
+
+pub struct Deserializer(pub Object);
+
+impl<'de> IntoDeserializer<'de, ConvertError> for Object {
+    type Deserializer = Deserializer;
+
+    fn into_deserializer(self) -> Deserializer {
+        Deserializer(self)
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer {
+    type Error = ConvertError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.0 {
+            Object::Nil => visitor.visit_unit(),
+            Object::Int(int) => visitor.visit_i64(int),
+            Object::Bool(bool) => visitor.visit_bool(bool),
+            Object::String(string) => visitor.visit_str(&string),
+            Object::Array(array) => {
+                let mut items = SeqDeserializer::new(array.0.into_iter());
+                let value = visitor.visit_seq(&mut items)?;
+                items.end()?;
+                Ok(value)
+            }
+            Object::HashTable(hash) => {
+                let mut entries = MapDeserializer::new(hash.0.into_iter());
+                let value = visitor.visit_map(&mut entries)?;
+                entries.end()?;
+                Ok(value)
+            }
+            Object::Return(value) => Deserializer(*value).deserialize_any(visitor),
+            other => Err(ConvertError::mismatch("plain data", &other)),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.0 {
+            Object::Nil => visitor.visit_none(),
+            other => visitor.visit_some(Deserializer(other)),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        match self.0 {
+            Object::String(variant) => visitor.visit_enum(variant.as_str().into_deserializer()),
+            Object::HashTable(hash) if hash.0.len() == 1 => {
+                let (variant, value) = hash.0.into_iter().next().unwrap();
+                visitor.visit_enum(EnumDeserializer { variant, value })
+            }
+            other => Err(ConvertError::mismatch(
+                "a string or a hash with a single key",
+                &other,
+            )),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+struct EnumDeserializer {
+    variant: Object,
+    value: Object,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumDeserializer {
+    type Error = ConvertError;
+    type Variant = Deserializer;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Deserializer)> {
+        let variant = seed.deserialize(Deserializer(self.variant))?;
+        Ok((variant, Deserializer(self.value)))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for Deserializer {
+    type Error = ConvertError;
+
+    fn unit_variant(self) -> Result<()> {
+        <()>::from_object(self.0)
+    }
+
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value> {
+        de::Deserializer::deserialize_any(self, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        de::Deserializer::deserialize_any(self, visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fnv::FnvHashMap;
+    use serde::{Deserialize, Serialize};
+
+    #[test]
+    fn convert_rust_values() {
+        assert_eq!(42u8.into_object(), Object::Int(42));
+        assert_eq!(i32::from_object(Object::Int(-7)), Ok(-7));
+        assert_eq!(
+            u8::from_object(Object::Int(300)),
+            Err(ConvertError::OutOfRange("300".into()))
+        );
+        assert_eq!(Some("hi").into_object(), Object::String("hi".into()));
+        assert_eq!(Option::<bool>::from_object(NIL), Ok(None));
+
+        let array = vec![(1, "one".to_string()), (2, "two".to_string())].into_object();
+        assert_eq!(array.to_string(), "[[1, one], [2, two]]");
+        assert_eq!(
+            Vec::<(i64, String)>::from_object(array),
+            Ok(vec![(1, "one".into()), (2, "two".into())])
+        );
+        assert_eq!(
+            bool::from_object(Object::Int(1)),
+            Err(ConvertError::Mismatch {
+                expected: "bool",
+                found: "1".into()
+            })
+        );
+        assert!(<(i64, i64)>::from_object(vec![1].into_object()).is_err());
+
+        let hash = HashMap::<_, _>::from_iter([("a", vec![1]), ("b", vec![])]).into_object();
+        let hash: FnvHashMap<String, Vec<i64>> = FromObject::from_object(hash).unwrap();
+        assert_eq!(hash["a"], [1]);
+        assert_eq!(hash["b"], Vec::<i64>::new());
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Shape {
+        Empty,
+        Circle(i64),
+        Rectangle { width: i64, height: i64 },
+        Line(i64, i64),
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Drawing {
+        name: String,
+        shapes: Vec<Shape>,
+        origin: (i64, i64),
+        tags: HashMap<String, bool>,
+        parent: Option<Box<Drawing>>,
+        visible: bool,
+    }
+
+    #[test]
+    fn round_trip_through_serde() {
+        let drawing = Drawing {
+            name: "sketch".into(),
+            shapes: vec![
+                Shape::Empty,
+                Shape::Circle(3),
+                Shape::Rectangle {
+                    width: 2,
+                    height: 5,
+                },
+                Shape::Line(1, 2),
+            ],
+            origin: (0, -1),
+            tags: HashMap::from_iter([("draft".to_string(), true)]),
+            parent: None,
+            visible: false,
+        };
+
+        let object = to_object(&drawing).unwrap();
+        let Object::HashTable(hash) = &object else {
+            panic!("expected a hash, found: {}", object);
+        };
+        assert_eq!(
+            hash.get("name".into_object()),
+            Some(&Object::String("sketch".into()))
+        );
+        let Some(Object::Array(shapes)) = hash.get("shapes".into_object()) else {
+            panic!("expected an array of shapes");
+        };
+        assert_eq!(shapes[0], Object::String("Empty".into()));
+        assert_eq!(shapes[1].to_string(), "{Circle: 3}");
+        assert_eq!(shapes[3].to_string(), "{Line: [1, 2]}");
+        assert_eq!(from_object::<Drawing>(object).unwrap(), drawing);
+    }
+
+    #[test]
+    fn report_unsupported_values() {
+        assert_eq!(
+            to_object(&1.5),
+            Err(ConvertError::Custom(
+                "floats aren't supported, found: 1.5".into()
+            ))
+        );
+        assert_eq!(
+            to_object(&u64::MAX),
+            Err(ConvertError::OutOfRange(u64::MAX.to_string()))
+        );
+        let error = from_object::<Drawing>(Object::Int(1)).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid type: integer `1`, expected struct Drawing"
+        );
+        let error = from_object::<Shape>(Object::String("Hexagon".into())).unwrap_err();
+        assert!(error.to_string().starts_with("unknown variant `Hexagon`"));
+    }
+}
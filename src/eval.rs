@@ -1,46 +1,176 @@
-use std::cell::RefCell;
-use std::iter;
-use std::rc::Rc;
+use std::{
+    cell::RefCell,
+    io::Write,
+    rc::{Rc, Weak},
+};
 
 use crate::ast::{
     BinaryExpression, BinaryOperator, BlockStatement, CallExpression, Expression,
-    FunctionExpression, IfExpression, IndexExpression, Literal, Statement, UnaryExpression,
+    FunctionExpression, IdentifierExpression, IfExpression, IndexExpression, Literal, Statement,
+    UnaryExpression, UnaryOperator,
 };
-use crate::object::{Array, BuiltInFn, Environment, Function, HashTable, Object, SharedEnv, NIL};
+use crate::object::{Array, BuiltInFn, Environment, EvalError, Function, Object, SharedEnv, NIL};
+use crate::optimizer::optimize;
 use crate::parser::Parser;
+use crate::resolver::{Binding, Resolver};
 use crate::token::Identifier;
-use anyhow::{anyhow, bail, Result};
+use anyhow::{anyhow, Result};
 
-#[derive(Default)]
+/// Clones share the same globals, which is how native functions get back to the program
+/// that calls them.
+#[derive(Clone)]
 pub struct Program {
     pub env: SharedEnv,
+    //receives the AST as json, after the optimizer ran, before it is evaluated
+    pub dump_ast: Option<Rc<RefCell<dyn Write>>>,
+    //folds constants before evaluating, only worth turning off to debug the optimizer
+    pub optimize: bool,
+    resolver: Rc<RefCell<Resolver>>,
+}
+
+/// A `Program` that doesn't keep its globals alive, for the functions stored in them.
+pub(crate) struct WeakProgram {
+    env: Weak<RefCell<Box<Environment>>>,
+    dump_ast: Option<Rc<RefCell<dyn Write>>>,
+    optimize: bool,
+    resolver: Weak<RefCell<Resolver>>,
+}
+
+impl WeakProgram {
+    pub(crate) fn upgrade(&self) -> Option<Program> {
+        Some(Program {
+            env: self.env.upgrade()?,
+            dump_ast: self.dump_ast.clone(),
+            optimize: self.optimize,
+            resolver: self.resolver.upgrade()?,
+        })
+    }
+}
+
+impl Default for Program {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Program {
     pub fn new() -> Self {
         Self {
-            env: Rc::new(RefCell::new(Box::new(Environment::new()))),
+            env: Environment::new_shared(),
+            dump_ast: None,
+            optimize: true,
+            resolver: Default::default(),
+        }
+    }
+
+    /// A program for interactive sessions, where a top-level `let` can define a name again
+    /// instead of failing. Scripts should use `new`, which catches accidental redefinitions.
+    pub fn interactive() -> Self {
+        Self {
+            resolver: Rc::new(RefCell::new(Resolver::interactive())),
+            ..Self::new()
+        }
+    }
+
+    pub(crate) fn downgrade(&self) -> WeakProgram {
+        WeakProgram {
+            env: Rc::downgrade(&self.env),
+            dump_ast: self.dump_ast.clone(),
+            optimize: self.optimize,
+            resolver: Rc::downgrade(&self.resolver),
+        }
+    }
+
+    pub fn eval(&mut self, mut parser: Parser) -> Result<Object> {
+        //released before evaluating, a native function may evaluate more code
+        self.resolver.borrow_mut().resolve(&mut parser.nodes)?;
+        if self.optimize {
+            optimize(&mut parser.nodes);
+        }
+        let result = self
+            .dump(&parser.nodes)
+            .and_then(|_| self.run(&parser.nodes));
+        if result.is_err() {
+            let env = self.env.borrow();
+            let is_set = |slot| env.get(0, slot).is_some();
+            self.resolver
+                .borrow_mut()
+                .forget_unset(&parser.nodes, is_set);
+        }
+        result
+    }
+
+    fn dump(&self, statements: &[Statement]) -> Result<()> {
+        if let Some(out) = &self.dump_ast {
+            let mut out = out.borrow_mut();
+            serde_json::to_writer_pretty(&mut *out, statements)?;
+            writeln!(out)?;
         }
+        Ok(())
     }
 
-    pub fn eval(&mut self, parser: Parser) -> Result<Object> {
+    fn run(&self, statements: &[Statement]) -> Result<Object> {
         let mut result = Object::Nil;
-        for statement in parser.nodes {
-            result = statement.eval(self.env.clone())?;
+        for statement in statements {
+            result = statement.eval(&self.env)?;
             if let Object::Return(inner) = result {
                 return Ok(*inner);
             }
         }
         Ok(result)
     }
+
+    /// Calls a function value with arguments that are already evaluated.
+    pub fn call(&self, function: &Object, args: Vec<Object>) -> Result<Object> {
+        let result = match function {
+            Object::Function(function) => function.call(args)?,
+            Object::BuiltInFn(builtin) => builtin.call(args)?,
+            Object::NativeFn(native) => native.call(&args)?,
+            value => return Err(anyhow!("expected a function, found: {value}")),
+        };
+        Ok(match result {
+            Object::Return(inner) => *inner,
+            result => result,
+        })
+    }
+
+    /// Value of a global defined by a previous `eval` or by `set_global`.
+    pub fn global(&self, name: &str) -> Option<Object> {
+        let slot = self.resolver.borrow().global(Identifier::lookup(name)?)?;
+        self.env.borrow().get(0, slot)
+    }
+
+    /// Every global that holds a value, in the order they were declared.
+    pub fn globals(&self) -> Vec<(&'static str, Object)> {
+        let slots = self.resolver.borrow().global_slots();
+        let env = self.env.borrow();
+        slots
+            .into_iter()
+            .filter_map(|(name, slot)| Some((name.as_str(), env.get(0, slot)?)))
+            .collect()
+    }
+
+    /// Defines or overwrites a global, visible to every program evaluated afterwards.
+    pub fn set_global(&self, name: &str, value: Object) {
+        let slot = self
+            .resolver
+            .borrow_mut()
+            .define_global(Identifier::new(name));
+        self.env.borrow_mut().set(slot, value);
+    }
 }
 
 impl Statement {
-    fn eval(self: Statement, env: SharedEnv) -> Result<Object> {
+    fn eval(&self, env: &SharedEnv) -> Result<Object> {
         match self {
-            Statement::Let { identifier, value } => {
-                let val = value.eval(env.clone())?;
-                env.borrow_mut().try_insert(&identifier, val)?;
+            Statement::Let {
+                identifier,
+                value,
+                slot,
+            } => {
+                let val = value.eval(env)?;
+                let slot = slot.ok_or(anyhow!("Identifier {} was not resolved", identifier))?;
+                env.borrow_mut().set(slot, val);
                 Ok(NIL)
             }
             Statement::Return(exp) => Ok(Object::Return(Box::new(exp.eval(env)?))),
@@ -50,10 +180,10 @@ impl Statement {
 }
 
 impl BlockStatement {
-    fn eval(self, env: SharedEnv) -> Result<Object> {
+    fn eval(&self, env: &SharedEnv) -> Result<Object> {
         let mut result = Object::Nil;
-        for statement in self.0 {
-            result = statement.eval(env.clone())?;
+        for statement in &self.0 {
+            result = statement.eval(env)?;
             if let Object::Return(_) = result {
                 break;
             }
@@ -63,24 +193,21 @@ impl BlockStatement {
 }
 
 impl Expression {
-    fn eval(self, env: SharedEnv) -> Result<Object> {
+    fn eval(&self, env: &SharedEnv) -> Result<Object> {
         Ok(match self {
             Expression::Literal(literal) => match literal {
-                Literal::Int(integer) => Object::Int(integer),
+                Literal::Int(integer) => Object::Int(*integer),
                 Literal::True => Object::Bool(true),
                 Literal::False => Object::Bool(false),
-                Literal::String(string) => Object::String(string),
+                Literal::String(string) => Object::String(string.clone()),
                 Literal::Nil => Object::Nil,
-                Literal::Hash(hash) => Object::HashTable(Box::new(HashTable::new(
-                    hash.into_iter()
-                        .map(|(key, value)| Ok((key.eval(env.clone())?, value.eval(env.clone())?)))
+                Literal::Hash(hash) => Object::HashTable(Box::new(
+                    hash.iter()
+                        .map(|(key, value)| Ok((key.eval(env)?, value.eval(env)?)))
                         .collect::<Result<_>>()?,
-                ))),
+                )),
                 Literal::Array(array) => Object::Array(Array::new(
-                    array
-                        .into_iter()
-                        .map(|x| x.eval(env.clone()))
-                        .collect::<Result<_>>()?,
+                    array.iter().map(|x| x.eval(env)).collect::<Result<_>>()?,
                 )),
             },
             Expression::Identifier(ident) => ident.eval(env)?,
@@ -95,38 +222,38 @@ impl Expression {
 }
 
 impl IndexExpression {
-    fn eval(self, env: SharedEnv) -> Result<Object> {
-        let container = self.container.eval(env.clone())?;
+    fn eval(&self, env: &SharedEnv) -> Result<Object> {
+        let container = self.container.eval(env)?;
         let index = self.index.eval(env)?;
-        Ok(match (container, index) {
-            (Object::Array(array), Object::Int(index)) => {
-                let value = if index.is_negative() {
-                    array.len().checked_sub(index.unsigned_abs() as usize)
-                } else {
-                    Some(index as usize)
-                };
-                value.map_or(NIL, |x| array.get(x).cloned().unwrap_or(NIL))
-            }
-            (Object::HashTable(table), anything) => table.0.get(&anything).cloned().unwrap_or(NIL),
-            (container, index) => bail!("index operator not supported: {}[{}]", container, index),
-        })
+        Ok(container.index(index)?)
     }
 }
 
 impl UnaryExpression {
-    fn eval(self, env: SharedEnv) -> Result<Object> {
-        let operand = self.value.eval(env)?;
-        Ok(match self.operator {
-            crate::ast::UnaryOperator::Not => operand.not()?,
-            crate::ast::UnaryOperator::Minus => operand.minus()?,
+    fn eval(&self, env: &SharedEnv) -> Result<Object> {
+        self.operator.apply(self.value.eval(env)?)
+    }
+}
+
+impl UnaryOperator {
+    pub(crate) fn apply(&self, operand: Object) -> Result<Object> {
+        Ok(match self {
+            UnaryOperator::Not => operand.not()?,
+            UnaryOperator::Minus => operand.minus()?,
         })
     }
 }
 
 impl BinaryExpression {
-    fn eval(self, env: SharedEnv) -> Result<Object> {
-        let (lhs, rhs) = (self.lhs.eval(env.clone())?, self.rhs.eval(env)?);
-        Ok(match self.operator {
+    fn eval(&self, env: &SharedEnv) -> Result<Object> {
+        let (lhs, rhs) = (self.lhs.eval(env)?, self.rhs.eval(env)?);
+        self.operator.apply(lhs, rhs)
+    }
+}
+
+impl BinaryOperator {
+    pub(crate) fn apply(&self, lhs: Object, rhs: Object) -> Result<Object> {
+        Ok(match self {
             BinaryOperator::Eq => lhs.eq(rhs).into(),
             BinaryOperator::NotEq => lhs.not_eq(rhs).into(),
             BinaryOperator::Lt => lhs.lt(rhs).into(),
@@ -141,114 +268,92 @@ impl BinaryExpression {
     }
 }
 
-impl Identifier {
-    fn eval(self, env: SharedEnv) -> Result<Object> {
-        env.borrow()
-            .get(&self)
-            .ok_or(anyhow!("Identifier {} not found", self.inner()))
+impl IdentifierExpression {
+    fn eval(&self, env: &SharedEnv) -> Result<Object> {
+        match self.binding {
+            Some(Binding::Local { depth, slot }) => env.borrow().get(depth, slot),
+            Some(Binding::Builtin(builtin)) => Some(Object::BuiltInFn(builtin)),
+            None => None,
+        }
+        .ok_or(anyhow!("Identifier {} not found", self.ident))
     }
 }
 
 impl CallExpression {
-    fn eval(self, env: SharedEnv) -> Result<Object> {
-        match self.function.eval(env.clone())? {
-            Object::Function(function) => function.eval(env, self.arguments),
-            Object::BuiltInFn(builtin) => builtin.eval(env, self.arguments),
+    fn eval(&self, env: &SharedEnv) -> Result<Object> {
+        let function = match self.function.as_ref() {
+            //`value["name"](...)` on a host object is a method call
+            Expression::IndexExpression(exp) => match exp.container.eval(env)? {
+                Object::Native(native) => {
+                    let name = exp.index.eval(env)?.into_string()?;
+                    return native.call_method(&name, &self.eval_arguments(env)?);
+                }
+                container => container.index(exp.index.eval(env)?)?,
+            },
+            function => function.eval(env)?,
+        };
+        match function {
+            Object::Function(function) => function.eval(env, &self.arguments),
+            Object::BuiltInFn(builtin) => builtin.eval(env, &self.arguments),
+            Object::NativeFn(native) => native.call(&self.eval_arguments(env)?),
             value => Err(anyhow!("expected a function, found: {value}")),
         }
     }
+
+    fn eval_arguments(&self, env: &SharedEnv) -> Result<Vec<Object>> {
+        self.arguments.iter().map(|x| x.eval(env)).collect()
+    }
 }
 
 impl BuiltInFn {
-    pub fn eval(self, env: SharedEnv, arguments: Vec<Expression>) -> Result<Object> {
-        match self {
-            //maybe use https://docs.rs/itertools/0.11.0/itertools/trait.Itertools.html#method.tuples
-            BuiltInFn::Len => match TryInto::<[Expression; 1]>::try_into(arguments) {
-                Ok([val]) => Ok(match val.eval(env)? {
-                    Object::String(val) => Object::Int(val.len() as i64),
-                    Object::Array(val) => Object::Int(val.len() as i64),
-                    val => bail!("expected array or string, found: {}", val),
-                }),
-                Err(vec) => bail!("expected 1 argument, found: {}", vec.len()),
-            },
-            BuiltInFn::First => match TryInto::<[Expression; 1]>::try_into(arguments) {
-                Ok([val]) => Ok(match val.eval(env)? {
-                    Object::Array(val) => val.first().cloned().unwrap_or(NIL),
-                    val => bail!("expected array, found: {}", val),
-                }),
-                Err(vec) => bail!("expected 1 argument, found: {}", vec.len()),
-            },
-            BuiltInFn::Last => match TryInto::<[Expression; 1]>::try_into(arguments) {
-                Ok([val]) => Ok(match val.eval(env)? {
-                    Object::Array(val) => val.last().cloned().unwrap_or(NIL),
-                    val => bail!("expected array, found: {}", val),
-                }),
-                Err(vec) => bail!("expected 1 argument, found: {}", vec.len()),
-            },
-            BuiltInFn::Rest => match TryInto::<[Expression; 1]>::try_into(arguments) {
-                Ok([val]) => Ok(match val.eval(env)? {
-                    Object::Array(val) => Array::new(val.0.into_iter().skip(1).collect()).into(),
-                    val => bail!("expected array, found: {}", val),
-                }),
-                Err(vec) => bail!("expected 1 argument, found: {}", vec.len()),
-            },
-            BuiltInFn::Push => match TryInto::<[Expression; 2]>::try_into(arguments) {
-                Ok([container, element]) => {
-                    Ok(match (container.eval(env.clone())?, element.eval(env)?) {
-                        (Object::Array(array), element) => {
-                            Array::new(array.0.into_iter().chain(iter::once(element)).collect())
-                                .into()
-                        }
-                        val => bail!("expected array and element, found: {} and {}", val.0, val.1),
-                    })
-                }
-                Err(vec) => bail!("expected 2 argument, found: {}", vec.len()),
-            },
-            BuiltInFn::Puts => {
-                for exp in arguments {
-                    println!("{}", exp.eval(env.clone())?)
-                }
-                Ok(NIL)
-            }
-        }
+    pub fn eval(self, env: &SharedEnv, arguments: &[Expression]) -> Result<Object> {
+        let args = arguments
+            .iter()
+            .map(|x| x.eval(env))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(self.call(args)?)
     }
 }
 
 impl Function {
-    fn eval(self, env: SharedEnv, arguments: Vec<Expression>) -> Result<Object> {
+    fn eval(&self, env: &SharedEnv, arguments: &[Expression]) -> Result<Object> {
         let args = arguments
-            .into_iter()
-            .map(|x| x.eval(env.clone()))
+            .iter()
+            .map(|x| x.eval(env))
             .collect::<Result<Vec<_>, _>>()?;
-        let extended_env = Environment::new_enclosed(
-            self.env.clone(),
-            self.parameters
-                .into_iter()
-                .map(|x| x.inner())
-                .zip(args)
-                .collect(),
-        );
-        self.body.eval(extended_env)
+        self.call(args)
+    }
+
+    fn call(&self, args: Vec<Object>) -> Result<Object> {
+        if args.len() != self.parameters.len() {
+            Err(EvalError::ArgumentCountError {
+                expected: self.parameters.len(),
+                found: args.len(),
+            })?
+        }
+        let extended_env = Environment::new_enclosed(self.env.clone(), args);
+        self.body.eval(&extended_env)
     }
 }
 
 impl FunctionExpression {
-    fn eval(self, env: SharedEnv) -> Result<Object> {
-        Ok(Object::Function(Box::new(Function::new(
-            self.parameters,
-            self.body,
-            env,
-        ))))
+    fn eval(&self, env: &SharedEnv) -> Result<Object> {
+        Ok(Object::Function(Rc::new(Function {
+            parameters: self.parameters.clone(),
+            body: self.body.clone(),
+            source: self.source.clone().unwrap_or_else(|| self.body.clone()),
+            env: env.clone(),
+        })))
     }
 }
 
 impl IfExpression {
-    fn eval(self, env: SharedEnv) -> Result<Object> {
-        let condition = self.condition.eval(env.clone())?.into_bool()?;
+    fn eval(&self, env: &SharedEnv) -> Result<Object> {
+        let condition = self.condition.eval(env)?.into_bool()?;
 
         Ok(if condition {
             self.consequence.eval(env)?
-        } else if let Some(alternative) = self.alternative {
+        } else if let Some(alternative) = &self.alternative {
             alternative.eval(env)?
         } else {
             NIL
@@ -257,39 +362,25 @@ impl IfExpression {
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
     use crate::{lexer::Lexer, parser::Parser};
     use insta::assert_yaml_snapshot;
 
-    fn parse_program(input: &str) -> Object {
-        let lexer = Lexer::new(input);
-        let parser = Parser::new(lexer);
-        let mut program = Program::new();
-
-        program.eval(parser).unwrap()
-    }
-
-    fn parse_test_input(input: &[&str]) -> Vec<Object> {
-        input.iter().map(|x| parse_program(x)).collect()
-    }
-
-    #[test]
-    fn eval_numeric_expression() {
-        let input = [
-            "5;",
-            "-10;",
-            "3 * (3 + 3) + 10;",
-            "(5 + 10 * 2 + 15 / 3) * 2 + -10;",
-        ];
-
-        let result = parse_test_input(input.as_slice());
-        assert_yaml_snapshot!(result);
-    }
-
-    #[test]
-    fn eval_recursive_fn() {
-        let input = "
+    /// Programs both backends must give the same results for, by snapshot name.
+    pub(crate) const CASES: &[(&str, &[&str])] = &[
+        (
+            "eval_numeric_expression",
+            &[
+                "5;",
+                "-10;",
+                "3 * (3 + 3) + 10;",
+                "(5 + 10 * 2 + 15 / 3) * 2 + -10;",
+            ],
+        ),
+        (
+            "eval_recursive_fn",
+            &["
             let fib = fn(x) {
                 if (x <= 2){
                     1
@@ -298,120 +389,116 @@ mod tests {
                 }
             };
             fib(10);
-        ";
-        let result = parse_test_input([input].as_slice());
-        assert_yaml_snapshot!(result);
-    }
-
-    #[test]
-    fn eval_array_map() {
-        let input = "
-        let map = fn(arr, f) {
-            let iter = fn(arr, accumulated) {
-                if (len(arr) == 0) {
-                    accumulated
-                } else {
-                    iter(rest(arr), push(accumulated, f(first(arr))));
-                }
+            "],
+        ),
+        (
+            "eval_array_map",
+            &["
+            let map = fn(arr, f) {
+                let iter = fn(arr, accumulated) {
+                    if (len(arr) == 0) {
+                        accumulated
+                    } else {
+                        iter(rest(arr), push(accumulated, f(first(arr))));
+                    }
+                };
+                iter(arr, []);
             };
-            iter(arr, []);
-        };
-        let a = [1, 2, 3, 4];
-        let double = fn(x) { x * 2 };
-        map(a, double);
-        ";
-
-        let result = parse_test_input([input].as_slice());
-        assert_yaml_snapshot!(result);
-    }
-
-    #[test]
-    fn eval_boolean_expression() {
-        let input = [
-            "true;",
-            "false;",
-            "1 >= 2;",
-            "1 >= 1;",
-            "1 <= 2;",
-            "1 <= 1;",
-            "1 > 2;",
-            "1 > 1;",
-            "1 < 2;",
-            "1 < 1;",
-            "!!0",
-            "!!fn(x){}",
-            "true == true;",
-            "false != false;",
-            r#"!!"""#,
-            r#"!!"something""#,
-        ];
-
-        let result = parse_test_input(input.as_slice());
-        assert_yaml_snapshot!(result);
-    }
-
-    #[test]
-    fn eval_not_expression() {
-        let input = [
-            "!5;", "!!5;", "!0;", "!!0;", "!true;", "!!true;", "!false;", "!!false;",
-        ];
-
-        let result = parse_test_input(input.as_slice());
-        assert_yaml_snapshot!(result);
-    }
-
-    #[test]
-    fn eval_array_literal() {
-        let input = [r#"[1, 2 * 2, "foo", fn(x) { return x + 2; }]"#];
-
-        let result = parse_test_input(input.as_slice());
-        assert_yaml_snapshot!(result);
-    }
-
-    #[test]
-    fn eval_array_index() {
-        let input = [
-            "[1, 2, 3][0]",
-            "[1, 2, 3][1]",
-            "[1, 2, 3][2]",
-            "let i = 0; [1][i];",
-            "[1, 2, 3][1 + 1];",
-            "let myArray = [1, 2, 3]; myArray[2];",
-            "let myArray = [1, 2, 3]; myArray[0] + myArray[1] + myArray[2];",
-            "let myArray = [1, 2, 3]; let i = myArray[0]; myArray[i]",
-            "[1, 2, 3][3]",
-            "[1, 2, 3][-1]",
-            "[1, 2, 3][-3]",
-            "[1, 2, 3][-4]",
-        ];
-
-        let result = parse_test_input(input.as_slice());
-        assert_yaml_snapshot!(result);
-    }
-
-    #[test]
-    fn eval_hash_index() {
-        let input = [
-            r#"{"foo": 5}["foo"]"#,
-            r#"{"foo": 4}["bar"]"#,
-            r#"let key = "foo"; {"foo": 3}[key]"#,
-            r#"{}["foo"]"#,
-            r#"{5: 2}[5]"#,
-            r#"{true: 5}[true]"#,
-            r#"{false: 5}[false]"#,
-            r#"{"true": 5}[true]"#,
-            r#"{true: 5}["true"]"#,
-            r#"{3: 1}["3"]"#,
-            r#"{"3": 1}[3]"#,
-        ];
-
-        let result = parse_test_input(input.as_slice());
-        assert_yaml_snapshot!(result);
-    }
-
-    #[test]
-    fn eval_hash_literal() {
-        let input = r#"
+            let a = [1, 2, 3, 4];
+            let double = fn(x) { x * 2 };
+            map(a, double);
+            "],
+        ),
+        (
+            "eval_boolean_expression",
+            &[
+                "true;",
+                "false;",
+                "1 >= 2;",
+                "1 >= 1;",
+                "1 <= 2;",
+                "1 <= 1;",
+                "1 > 2;",
+                "1 > 1;",
+                "1 < 2;",
+                "1 < 1;",
+                "!!0",
+                "!!fn(x){}",
+                "true == true;",
+                "false != false;",
+                r#"!!"""#,
+                r#"!!"something""#,
+            ],
+        ),
+        (
+            "eval_not_expression",
+            &[
+                "!5;", "!!5;", "!0;", "!!0;", "!true;", "!!true;", "!false;", "!!false;",
+            ],
+        ),
+        (
+            "eval_array_literal",
+            &[r#"[1, 2 * 2, "foo", fn(x) { return x + 2; }]"#],
+        ),
+        (
+            "eval_array_index",
+            &[
+                "[1, 2, 3][0]",
+                "[1, 2, 3][1]",
+                "[1, 2, 3][2]",
+                "let i = 0; [1][i];",
+                "[1, 2, 3][1 + 1];",
+                "let myArray = [1, 2, 3]; myArray[2];",
+                "let myArray = [1, 2, 3]; myArray[0] + myArray[1] + myArray[2];",
+                "let myArray = [1, 2, 3]; let i = myArray[0]; myArray[i]",
+                "[1, 2, 3][3]",
+                "[1, 2, 3][-1]",
+                "[1, 2, 3][-3]",
+                "[1, 2, 3][-4]",
+            ],
+        ),
+        (
+            "eval_hash_index",
+            &[
+                r#"{"foo": 5}["foo"]"#,
+                r#"{"foo": 4}["bar"]"#,
+                r#"let key = "foo"; {"foo": 3}[key]"#,
+                r#"{}["foo"]"#,
+                r#"{5: 2}[5]"#,
+                r#"{true: 5}[true]"#,
+                r#"{false: 5}[false]"#,
+                r#"{"true": 5}[true]"#,
+                r#"{true: 5}["true"]"#,
+                r#"{3: 1}["3"]"#,
+                r#"{"3": 1}[3]"#,
+            ],
+        ),
+        (
+            "eval_if_expression",
+            &[
+                "if (true) { 10; };",
+                "if (false) { 10; };",
+                "if (1) { 10; };",
+                "if (1 < 2) { 10; };",
+                "if (1 > 2) { 10; };",
+                "if (1 > 2) { 10; } else { 20; };",
+                "if (1 < 2) { 10; } else { 20; };",
+            ],
+        ),
+        (
+            "eval_return_statement",
+            &[
+                "return 10;",
+                "return 10; 9;",
+                "return 2 * 5; 9;",
+                "9; return 2 * 5; 9;",
+                "if (true) { return 10; }; return 9;",
+                "if (true) { if (true) { return 10; }; return 1;};",
+            ],
+        ),
+        (
+            "eval_hash_literal",
+            &[r#"
             let two = "potato";
             {
                 "one": 1 + 1,
@@ -420,182 +507,203 @@ mod tests {
                 4: {1:1},
                 true: [1,2],
                 "false": 6
-            };"#;
-
-        let result = parse_program(input);
-        if let Object::HashTable(hash) = result {
-            let mut result = hash
-                .0
-                .into_iter()
-                .map(|(key, value)| (key.to_string(), value))
-                .collect::<Vec<_>>();
-            result.sort_by(|(k1, _), (k2, _)| k1.cmp(k2));
-            assert_yaml_snapshot!(result);
-        } else {
-            panic!("expected hash table");
+            };"#],
+        ),
+        ("eval_string_concat", &[r#""hello" + "world""#]),
+        (
+            "eval_builtin_len",
+            &[
+                r#"len("")"#,
+                r#"len("four")"#,
+                r#"len("hello world")"#,
+                r#"len(1)"#,
+                r#"len("one", "two")"#,
+            ],
+        ),
+        (
+            "eval_let_statements",
+            &[
+                "let a = 5; a;",
+                "let a = 5 * 5; a;",
+                "let a = 5; let b = a; b;",
+                "let a = 5; let b = a; let c = a + b + 5; c;",
+            ],
+        ),
+        (
+            "eval_function_declaration",
+            &[
+                "fn(x) { x + 2; };",
+                "fn(x) { let a = 2; if (true) { a * 3 + x } else { 0 } }",
+                "let f = fn() { 1 + 2 }; [f(), f]",
+            ],
+        ),
+        (
+            "eval_function_call",
+            &[
+                "let identity = fn(x) { x; }; identity(5);",
+                "let identity = fn(x) { return x; }; identity(5);",
+                "let double = fn(x) { x * 2; }; double(5);",
+                "let add = fn(x, y) { x + y; }; add(5, 5);",
+                "let add = fn(x, y) { x + y; }; add(5 + 5, add(5, 5));",
+                "fn(x) { x; }(5)",
+                "fn(x) { x }(1, 2)",
+                "fn(x, y) { x }(1)",
+                "1(2)",
+            ],
+        ),
+        (
+            "eval_closure",
+            &[
+                "
+                let newAdder = fn(x) {fn(y) { x + y };};
+                let addTwo = newAdder(2);
+                addTwo(2);
+                ",
+                "
+                let newAdder = fn(x) {fn(y) { x + y };};
+                let addTwo = newAdder(2);
+                let addEight = newAdder(8);
+                let addTen = fn(x) { addTwo(addEight(x)) };
+                addTen(5);
+                ",
+                "
+                let add = fn(a, b) { a + b };
+                let applyFunc = fn(a, b, func) { func(a, b) };
+                applyFunc(10, 2, add);
+                ",
+            ],
+        ),
+        (
+            "eval_type_errors",
+            &[
+                "5 + true;",
+                "5 + true; 5;",
+                "-true;",
+                "true + false;",
+                "true + false + true + false;",
+                "5; true + false; 5",
+                "if (10 > 1) {
+                    if (10 > 1) {
+                        return true + false;
+                    };
+                    return 1;
+                };",
+                "foobar;",
+                "let foo = 3; let foo = 4;",
+                r#""Hello" - "World""#,
+                "[1][true]",
+            ],
+        ),
+        (
+            "eval_resolves_before_running",
+            &[
+                "5 + true; foobar",
+                "let f = fn() { missing }; 1",
+                "x; let x = 1;",
+                "
+                let isEven = fn(n) { if (n == 0) { true } else { isOdd(n - 1) } };
+                let isOdd = fn(n) { if (n == 0) { false } else { isEven(n - 1) } };
+                isEven(10)
+                ",
+                "let len = fn(x) { 0 }; len([1, 2])",
+            ],
+        ),
+        (
+            "eval_integer_errors",
+            &[
+                "1 / 0",
+                "let zero = 0; 1 / zero",
+                "9223372036854775807 + 1",
+                "-9223372036854775807 - 2",
+                "4611686018427387904 * 2",
+                "(-9223372036854775807 - 1) / -1",
+                "-(-9223372036854775807 - 1)",
+                "let f = fn(x) { x * x }; f(4294967296)",
+            ],
+        ),
+    ];
+
+    /// The value of a program as Monkey code, or the error it failed with.
+    pub(crate) fn outcome(result: Result<Object>) -> String {
+        match result {
+            Ok(value) => value.inspect(80),
+            Err(err) => format!("error: {}", err),
         }
     }
 
-    #[test]
-    fn eval_if_expression() {
-        let input = [
-            "if (true) { 10; };",
-            "if (false) { 10; };",
-            "if (1) { 10; };",
-            "if (1 < 2) { 10; };",
-            "if (1 > 2) { 10; };",
-            "if (1 > 2) { 10; } else { 20; };",
-            "if (1 < 2) { 10; } else { 20; };",
-        ];
-
-        let result = parse_test_input(input.as_slice());
-        assert_yaml_snapshot!(result);
-    }
-
-    #[test]
-    fn eval_return_statement() {
-        let input = [
-            "return 10;",
-            "return 10; 9;",
-            "return 2 * 5; 9;",
-            "9; return 2 * 5; 9;",
-            "if (true) { return 10; }; return 9;",
-            "if (true) { if (true) { return 10; }; return 1;};",
-        ];
-
-        let result = parse_test_input(input.as_slice());
-        assert_yaml_snapshot!(result);
-    }
-
-    #[test]
-    fn eval_string_concat() {
-        let input = [r#""hello" + "world""#];
-
-        let result = parse_test_input(input.as_slice());
-        assert_yaml_snapshot!(result);
-    }
+    fn parse_program(input: &str) -> Object {
+        let lexer = Lexer::new(input);
+        let parser = Parser::new(lexer);
+        let mut program = Program::new();
 
-    #[test]
-    fn eval_builtin_len() {
-        let input = [
-            r#"len("")"#,
-            r#"len("four")"#,
-            r#"len("hello world")"#,
-            r#"len(1)"#,
-            r#"len("one", "two")"#,
-        ];
-
-        let result: Vec<_> = input
-            .iter()
-            .map(|x| {
-                let lexer = Lexer::new(x);
-                let parser = Parser::new(lexer);
-                let mut program = Program::new();
-                match program.eval(parser) {
-                    Ok(ok) => ok.to_string(),
-                    Err(err) => err.to_string(),
-                }
-            })
-            .collect();
-        assert_yaml_snapshot!(result);
+        program.eval(parser).unwrap()
     }
 
     #[test]
-    fn eval_let_statements() {
-        let input = [
-            "let a = 5; a;",
-            "let a = 5 * 5; a;",
-            "let a = 5; let b = a; b;",
-            "let a = 5; let b = a; let c = a + b + 5; c;",
-        ];
-
-        let result = parse_test_input(input.as_slice());
-        assert_yaml_snapshot!(result);
+    fn eval_cases() {
+        for (name, inputs) in CASES {
+            let result: Vec<_> = inputs
+                .iter()
+                .map(|x| outcome(Program::new().eval(Parser::new(Lexer::new(x)))))
+                .collect();
+            assert_yaml_snapshot!(*name, result);
+        }
     }
 
     #[test]
-    fn eval_function_declaration() {
-        let input = ["fn(x) { x + 2; };"];
+    fn eval_shares_function_bodies() {
+        let result = parse_program("let make = fn() { fn(x) { x } }; [make(), make()]");
 
-        let result = parse_test_input(input.as_slice());
-        assert_yaml_snapshot!(result);
+        let Object::Array(array) = result else {
+            panic!("expected array")
+        };
+        let [Object::Function(lhs), Object::Function(rhs)] = array.0.as_slice() else {
+            panic!("expected two functions")
+        };
+        assert!(!Rc::ptr_eq(lhs, rhs));
+        assert!(Rc::ptr_eq(&lhs.body, &rhs.body));
     }
 
     #[test]
-    fn eval_function_call() {
-        let input = [
-            "let identity = fn(x) { x; }; identity(5);",
-            "let identity = fn(x) { return x; }; identity(5);",
-            "let double = fn(x) { x * 2; }; double(5);",
-            "let add = fn(x, y) { x + y; }; add(5, 5);",
-            "let add = fn(x, y) { x + y; }; add(5 + 5, add(5, 5));",
-            "fn(x) { x; }(5)",
-        ];
+    fn shadow_globals_interactively() {
+        let mut program = Program::interactive();
+        let mut eval = |input| program.eval(Parser::new(Lexer::new(input))).unwrap();
+        eval("let x = 1; let get = fn() { x };");
+        eval("let x = x + 10;");
+        assert_eq!(eval("[x, get()]").to_string(), "[11, 1]");
+
+        eval("let f = fn(n) { 100 };");
+        eval("let f = fn(n) { if (n == 0) { 0 } else { f(n - 1) } };");
+        assert_eq!(eval("f(3)"), Object::Int(0));
+
+        //a redefinition that fails keeps the previous value
+        let error = program.eval(Parser::new(Lexer::new("let x = 2; let x = 1 + true;")));
+        assert!(error.is_err());
+        let error = program.eval(Parser::new(Lexer::new("let y = 1 + true;")));
+        assert!(error.is_err());
+        let mut eval = |input| program.eval(Parser::new(Lexer::new(input))).unwrap();
+        assert_eq!(eval("[x, get()]").to_string(), "[2, 1]");
+        eval("let y = 3;");
+        assert_eq!(eval("x + y"), Object::Int(5));
 
-        let result = parse_test_input(input.as_slice());
-        assert_yaml_snapshot!(result);
-    }
-
-    #[test]
-    fn eval_closure() {
-        let input = [
-            "
-            let newAdder = fn(x) {fn(y) { x + y };};
-            let addTwo = newAdder(2);
-            addTwo(2);
-            ",
-            "
-            let newAdder = fn(x) {fn(y) { x + y };};
-            let addTwo = newAdder(2);
-            let addEight = newAdder(8);
-            let addTen = fn(x) { addTwo(addEight(x)) };
-            addTen(5);
-            ",
-            "
-            let add = fn(a, b) { a + b };
-            let applyFunc = fn(a, b, func) { func(a, b) };
-            applyFunc(10, 2, add);
-            ",
-        ];
-
-        let result = parse_test_input(input.as_slice());
-        assert_yaml_snapshot!(result);
+        let mut program = Program::new();
+        program.eval(Parser::new(Lexer::new("let x = 1;"))).unwrap();
+        let error = program.eval(Parser::new(Lexer::new("let x = 2;")));
+        assert_eq!(
+            error.unwrap_err().to_string(),
+            "Identifier x already defined"
+        );
     }
 
     #[test]
-    fn eval_type_errors() {
-        let input = [
-            "5 + true;",
-            "5 + true; 5;",
-            "-true;",
-            "true + false;",
-            "true + false + true + false;",
-            "5; true + false; 5",
-            "if (10 > 1) {
-                if (10 > 1) {
-                    return true + false;
-                };
-                return 1;
-            };",
-            "foobar;",
-            "let foo = 3; let foo = 4;",
-            r#""Hello" - "World""#,
-        ];
-
-        let result: Vec<String> = input
-            .iter()
-            .flat_map(|x| {
-                let lexer = Lexer::new(x);
-                let parser = Parser::new(lexer);
-                let env = Environment::new();
-                let mut program = Program::new();
-                match program.eval(parser) {
-                    Ok(val) => None,
-                    Err(err) => Some(err.to_string()),
-                }
-            })
-            .collect();
+    fn list_globals() {
+        let mut program = Program::new();
+        program.set_global("host", Object::Int(0));
+        let input = "let b = 2; let a = fn() { let local = 1; local };";
+        program.eval(Parser::new(Lexer::new(input))).unwrap();
+        assert!(program.eval(Parser::new(Lexer::new("let c = d;"))).is_err());
 
-        assert_yaml_snapshot!(result);
+        let names: Vec<_> = program.globals().into_iter().map(|x| x.0).collect();
+        assert_eq!(names, ["host", "b", "a"]);
     }
 }
@@ -0,0 +1,111 @@
+use anyhow::{anyhow, Result};
+
+use crate::{lexer::Lexer, parser::Parser, printer::Printer, token::Token};
+
+pub const WIDTH: usize = 80;
+
+/// Formats Monkey source the canonical way, keeping its comments and the blank lines
+/// between statements. Fails when the source doesn't parse.
+pub fn format(source: &str) -> Result<String> {
+    let mut lexer = Lexer::new(source);
+    //the printer decides on its own where parentheses, commas and semicolons go, so
+    //comments are attached to the index of a token among the remaining ones
+    let mut significant = Vec::with_capacity(lexer.tokens.len() + 1);
+    let mut count = 0;
+    for token in &lexer.tokens {
+        significant.push(count);
+        if !matches!(
+            token,
+            Token::LParen | Token::RParen | Token::Comma | Token::Semicolon
+        ) {
+            count += 1;
+        }
+    }
+    significant.push(count);
+
+    let mut comments = std::mem::take(&mut lexer.comments);
+    for comment in &mut comments {
+        comment.token = significant[comment.token];
+    }
+    let blank_lines = lexer.blank_lines.iter().map(|x| significant[*x]).collect();
+
+    let parser = Parser::new(lexer);
+    if let Some(error) = parser.errors.into_iter().next() {
+        return Err(anyhow!("could not parse the source: {}", error));
+    }
+    let mut printer = Printer::with_trivia(WIDTH, comments, blank_lines);
+    printer.print_program(&parser.nodes);
+
+    let mut out = printer.finish().trim_end().to_string();
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use insta::assert_snapshot;
+
+    fn check(input: &str) -> String {
+        let formatted = format(input).unwrap();
+        assert_eq!(format(&formatted).unwrap(), formatted, "not idempotent");
+        assert_eq!(
+            Parser::new(Lexer::new(&formatted)).nodes,
+            Parser::new(Lexer::new(input)).nodes
+        );
+        formatted
+    }
+
+    #[test]
+    fn format_layout() {
+        let input = r#"
+            let   add=fn(a,b){return a+b;};
+            let fib = fn(x) { if (x <= 2) { 1 } else { fib(x - 1) + fib(x - 2) } };
+            let numbers = [1, 2, 3,];
+
+
+            let people = [{"name": "Alice", "age": 24}, {"name": "Bob", "age": 99}, {"name": "Carol"}];
+            let reduce = fn(array, initial, function_with_a_long_name, another_long_parameter) { initial };
+            puts(add(1, 2), map(numbers, fn(x) { x * 2 }), "a long string to push it over the limit")
+        "#;
+
+        assert_snapshot!(check(input));
+    }
+
+    #[test]
+    fn format_keeps_comments() {
+        let input = r#"
+            // computes things
+            let a = 1; // one
+
+            // after a blank line
+            let list = [
+                1, // first
+                // the second
+                2
+            ];
+            let f = fn(x) {
+                // nothing yet
+            };
+            if (a) { a // the value
+            }
+            // the end
+        "#;
+
+        assert_snapshot!(check(input));
+    }
+
+    #[test]
+    fn format_keeps_non_ascii_text() {
+        let input = "let s  =  \"héllo wörld 🐒\"; // ünïcode\n";
+        assert_eq!(check(input), "let s = \"héllo wörld 🐒\"; // ünïcode\n");
+    }
+
+    #[test]
+    fn format_rejects_invalid_source() {
+        assert!(format("let = 1;").is_err());
+        assert_eq!(format("  \n").unwrap(), "");
+    }
+}
@@ -0,0 +1,251 @@
+use std::{
+    cell::{Cell, RefCell},
+    mem,
+    rc::{Rc, Weak},
+};
+
+use fnv::FnvHashMap;
+
+use crate::object::{Environment, Function, Object, SharedEnv};
+
+const MIN_THRESHOLD: usize = 1024;
+
+/// Keeps track of every environment created by a program.
+///
+/// A recursive `let f = fn…` stores the function in the environment it captures, so that
+/// environment keeps itself alive through an `Rc` cycle. `collect` finds the environments
+/// that are only referenced by each other and clears them, which breaks those cycles.
+#[derive(Clone)]
+pub struct Heap(Rc<HeapInner>);
+
+struct HeapInner {
+    envs: RefCell<Vec<Weak<RefCell<Box<Environment>>>>>,
+    //number of allocations that triggers the next collection
+    threshold: Cell<usize>,
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+//an environment or a function, with the references it holds to other nodes
+struct Node {
+    strong: usize,
+    internal: usize,
+    pinned: bool,
+    edges: Vec<usize>,
+}
+
+impl Node {
+    fn new(strong: usize) -> Self {
+        Self {
+            strong,
+            internal: 0,
+            pinned: false,
+            edges: Vec::new(),
+        }
+    }
+}
+
+struct Graph {
+    nodes: Vec<Node>,
+    envs: FnvHashMap<*const RefCell<Box<Environment>>, usize>,
+    functions: FnvHashMap<*const Function, usize>,
+}
+
+impl Graph {
+    fn scan_value(&mut self, from: usize, value: &Object) {
+        match value {
+            Object::Function(function) => {
+                let ptr = Rc::as_ptr(function);
+                let to = match self.functions.get(&ptr) {
+                    Some(index) => *index,
+                    None => {
+                        let index = self.nodes.len();
+                        self.nodes.push(Node::new(Rc::strong_count(function)));
+                        self.functions.insert(ptr, index);
+                        if let Some(env) = self.envs.get(&Rc::as_ptr(&function.env)) {
+                            self.link(index, *env);
+                        }
+                        index
+                    }
+                };
+                self.link(from, to);
+            }
+            Object::Array(array) => array.0.iter().for_each(|x| self.scan_value(from, x)),
+            Object::HashTable(hash) => hash.0.iter().for_each(|(key, value)| {
+                self.scan_value(from, key);
+                self.scan_value(from, value);
+            }),
+            Object::Return(value) => self.scan_value(from, value),
+            _ => {}
+        }
+    }
+
+    fn link(&mut self, from: usize, to: usize) {
+        self.nodes[from].edges.push(to);
+        self.nodes[to].internal += 1;
+    }
+
+    //everything reachable from a node referenced from outside the graph must stay alive
+    fn mark(&self) -> Vec<bool> {
+        let mut marked = vec![false; self.nodes.len()];
+        let mut stack: Vec<usize> = (0..self.nodes.len())
+            .filter(|x| self.nodes[*x].pinned || self.nodes[*x].strong > self.nodes[*x].internal)
+            .collect();
+        while let Some(index) = stack.pop() {
+            if !mem::replace(&mut marked[index], true) {
+                stack.extend(&self.nodes[index].edges);
+            }
+        }
+        marked
+    }
+}
+
+impl Heap {
+    pub fn new() -> Self {
+        Self(Rc::new(HeapInner {
+            envs: RefCell::new(Vec::new()),
+            threshold: Cell::new(MIN_THRESHOLD),
+        }))
+    }
+
+    pub fn alloc(&self, env: Environment) -> SharedEnv {
+        let env = Rc::new(RefCell::new(Box::new(env)));
+        let allocated = {
+            let mut envs = self.0.envs.borrow_mut();
+            envs.push(Rc::downgrade(&env));
+            envs.len()
+        };
+        if allocated >= self.0.threshold.get() {
+            self.collect();
+        }
+        env
+    }
+
+    /// Number of environments still alive.
+    pub fn live(&self) -> usize {
+        self.0
+            .envs
+            .borrow()
+            .iter()
+            .filter(|x| x.strong_count() > 0)
+            .count()
+    }
+
+    /// Clears the environments only reachable through cycles, returning how many were freed.
+    pub fn collect(&self) -> usize {
+        let envs: Vec<SharedEnv> = {
+            let mut envs = self.0.envs.borrow_mut();
+            envs.retain(|x| x.strong_count() > 0);
+            envs.iter().filter_map(Weak::upgrade).collect()
+        };
+        let mut graph = Graph {
+            //the upgrade above is the only reference we add
+            nodes: envs
+                .iter()
+                .map(|x| Node::new(Rc::strong_count(x) - 1))
+                .collect(),
+            envs: envs
+                .iter()
+                .enumerate()
+                .map(|(index, env)| (Rc::as_ptr(env), index))
+                .collect(),
+            functions: FnvHashMap::default(),
+        };
+        for (index, env) in envs.iter().enumerate() {
+            //an environment being evaluated right now is certainly alive
+            let Ok(env) = env.try_borrow() else {
+                graph.nodes[index].pinned = true;
+                continue;
+            };
+            if let Some(outer) = env
+                .outer
+                .as_ref()
+                .and_then(|x| graph.envs.get(&Rc::as_ptr(x)))
+            {
+                graph.link(index, *outer);
+            }
+            env.curr
+                .iter()
+                .flatten()
+                .for_each(|value| graph.scan_value(index, value));
+        }
+        let marked = graph.mark();
+        //values are dropped once every borrow is released, they may hold other environments
+        let garbage: Vec<_> = envs
+            .iter()
+            .zip(&marked)
+            .filter(|(_, marked)| !**marked)
+            .map(|(env, _)| {
+                let mut env = env.borrow_mut();
+                (mem::take(&mut env.curr), env.outer.take())
+            })
+            .collect();
+        let freed = garbage.len();
+        drop(garbage);
+        drop(envs);
+
+        let survivors = self.live();
+        self.0.threshold.set(MIN_THRESHOLD.max(survivors * 2));
+        freed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{eval::Program, lexer::Lexer, parser::Parser};
+
+    fn eval(program: &mut Program, input: &str) -> Object {
+        program.eval(Parser::new(Lexer::new(input))).unwrap()
+    }
+
+    #[test]
+    fn collect_recursive_closures() {
+        let mut program = Program::new();
+        let heap = program.env.borrow().heap().clone();
+        let input = "
+            let make = fn(n) { let count = fn(x) { if (x == 0) { n } else { count(x - 1) } }; count };
+            make(1)(3);
+            make(2)(3);
+        ";
+        assert_eq!(eval(&mut program, input), Object::Int(2));
+
+        let counter = eval(&mut program, "let counter = make(3); make(4); counter(2)");
+        assert_eq!(counter, Object::Int(3));
+        assert_eq!(heap.collect(), 3);
+        assert_eq!(heap.live(), 2);
+        assert_eq!(eval(&mut program, "counter(1)"), Object::Int(3));
+    }
+
+    #[test]
+    fn keep_environments_referenced_from_outside() {
+        let mut program = Program::new();
+        let heap = program.env.borrow().heap().clone();
+        let function = eval(&mut program, "fn() { let f = fn() { f }; f }()");
+
+        assert_eq!(heap.collect(), 0);
+        drop(function);
+        assert_eq!(heap.collect(), 1);
+        assert_eq!(heap.live(), 1);
+    }
+
+    #[test]
+    fn collect_during_evaluation() {
+        let mut program = Program::new();
+        let heap = program.env.borrow().heap().clone();
+        let input = "
+            let tree = fn(n) {
+                let inner = fn(x) { if (x == 0) { 0 } else { inner(x - 1) } };
+                if (n == 0) { inner(1) } else { tree(n - 1) + tree(n - 1) }
+            };
+            tree(11)
+        ";
+
+        assert_eq!(eval(&mut program, input), Object::Int(0));
+        assert!(heap.live() < MIN_THRESHOLD * 2);
+    }
+}
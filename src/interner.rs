@@ -0,0 +1,121 @@
+use std::{
+    cell::RefCell,
+    fmt::{self, Display},
+    sync::{LazyLock, Mutex, MutexGuard, PoisonError},
+};
+
+use fnv::FnvHashMap;
+
+/// A name from the source interned for the lifetime of the process, compared and hashed as an
+/// integer. Strings built at runtime are never interned since nothing is ever freed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+#[derive(Default)]
+struct Interner {
+    symbols: FnvHashMap<&'static str, Symbol>,
+    //interned strings are never freed, so handing out `&'static str` is sound
+    names: Vec<&'static str>,
+}
+
+static INTERNER: LazyLock<Mutex<Interner>> = LazyLock::new(Default::default);
+
+thread_local! {
+    //the part of the interner this thread has seen, so only a name new to the thread takes
+    //the lock, and printing a symbol never does
+    static CACHE: RefCell<Interner> = Default::default();
+}
+
+fn interner() -> MutexGuard<'static, Interner> {
+    INTERNER.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl Symbol {
+    pub fn intern(name: &str) -> Self {
+        CACHE.with_borrow_mut(|cache| {
+            if let Some(symbol) = cache.symbols.get(name) {
+                return *symbol;
+            }
+            let mut interner = interner();
+            let (name, symbol) = match interner.symbols.get_key_value(name) {
+                Some((name, symbol)) => (*name, *symbol),
+                None => {
+                    let symbol = Symbol(interner.names.len() as u32);
+                    let name: &'static str = Box::leak(name.into());
+                    interner.names.push(name);
+                    interner.symbols.insert(name, symbol);
+                    (name, symbol)
+                }
+            };
+            cache.symbols.insert(name, symbol);
+            symbol
+        })
+    }
+
+    /// The symbol of `name`, without interning it if it wasn't already.
+    pub fn lookup(name: &str) -> Option<Self> {
+        CACHE.with_borrow_mut(|cache| {
+            if let Some(symbol) = cache.symbols.get(name) {
+                return Some(*symbol);
+            }
+            let (name, symbol) = interner()
+                .symbols
+                .get_key_value(name)
+                .map(|(x, y)| (*x, *y))?;
+            cache.symbols.insert(name, symbol);
+            Some(symbol)
+        })
+    }
+
+    pub fn as_str(self) -> &'static str {
+        let index = self.0 as usize;
+        CACHE.with_borrow_mut(|cache| {
+            if index >= cache.names.len() {
+                let interner = interner();
+                cache
+                    .names
+                    .extend_from_slice(&interner.names[cache.names.len()..]);
+            }
+            cache.names[index]
+        })
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intern_strings() {
+        let foo = Symbol::intern("interner_test_foo");
+        let bar = Symbol::intern("interner_test_bar");
+
+        assert_eq!(Symbol::intern("interner_test_foo"), foo);
+        assert_ne!(foo, bar);
+        assert_eq!(foo.as_str(), "interner_test_foo");
+        assert_eq!(bar.to_string(), "interner_test_bar");
+        assert_eq!(Symbol::lookup("interner_test_bar"), Some(bar));
+        assert_eq!(Symbol::lookup("interner_test_baz"), None);
+    }
+
+    #[test]
+    fn share_symbols_between_threads() {
+        let foo = Symbol::intern("interner_thread_foo");
+        let (bar, foo_there) = std::thread::spawn(move || {
+            let bar = Symbol::intern("interner_thread_bar");
+            (bar, (foo.as_str(), Symbol::intern("interner_thread_foo")))
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(foo_there, ("interner_thread_foo", foo));
+        assert_eq!(bar.as_str(), "interner_thread_bar");
+        assert_eq!(Symbol::lookup("interner_thread_bar"), Some(bar));
+    }
+}
@@ -0,0 +1,402 @@
+use std::{cell::RefCell, fs, io::Write, mem, path::Path, rc::Rc};
+
+use anyhow::{anyhow, Context, Result};
+use thiserror::Error;
+
+use crate::{
+    compiler::{Bytecode, Compiler},
+    eval::Program,
+    lexer::Lexer,
+    object::{BuiltInFn, NativeFn, Object},
+    parser::Parser,
+    vm::Vm,
+};
+
+/// Every error found while parsing a source, one per line.
+#[derive(Error, Debug)]
+#[error("{}", .0.iter().map(|x| x.to_string()).collect::<Vec<_>>().join("\n"))]
+pub struct ParseError(pub Vec<anyhow::Error>);
+
+/// Entry point for embedding Monkey in a Rust program.
+///
+/// Globals defined by one call are visible to the next ones, so a host can load a script
+/// once and then call into it.
+///
+/// ```
+/// use monkey_rust::{Interpreter, Object};
+///
+/// let mut interpreter = Interpreter::new();
+/// interpreter.set_global("base", Object::Int(10));
+/// interpreter.eval_str("let add = fn(x) { base + x };").unwrap();
+///
+/// let sum = interpreter.call_function("add", vec![Object::Int(5)]).unwrap();
+/// assert_eq!(sum, Object::Int(15));
+/// ```
+#[derive(Default)]
+pub struct Interpreter {
+    program: Program,
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self {
+            program: Program::new(),
+        }
+    }
+
+    /// Evaluates `source`, returning the value of its last statement. A source that doesn't
+    /// parse fails with a `ParseError`.
+    pub fn eval_str(&mut self, source: &str) -> Result<Object> {
+        self.program.eval(parse(source)?)
+    }
+
+    pub fn eval_file(&mut self, path: impl AsRef<Path>) -> Result<Object> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path)
+            .with_context(|| format!("could not read {}", path.display()))?;
+        self.eval_str(&source)
+            .with_context(|| path.display().to_string())
+    }
+
+    /// Compiles the script at `path` for the bytecode vm and writes it to `out`, so it can be
+    /// shipped without its source. `globals` are the names the host gives a value to when
+    /// running it, see `run_compiled_file`.
+    pub fn compile_file(
+        path: impl AsRef<Path>,
+        out: impl AsRef<Path>,
+        globals: &[&str],
+    ) -> Result<()> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path)
+            .with_context(|| format!("could not read {}", path.display()))?;
+        let mut compiler = Compiler::new();
+        for name in globals {
+            compiler.declare_global(name)?;
+        }
+        let bytecode = parse(&source)
+            .and_then(|parser| compiler.compile(parser))
+            .with_context(|| path.display().to_string())?;
+        bytecode.write_file(out)
+    }
+
+    /// Runs a file written by `compile_file`, with the values of its `globals` in the same
+    /// order. Compiled programs run on their own, they don't see the globals or the native
+    /// functions of an interpreter.
+    pub fn run_compiled_file(path: impl AsRef<Path>, globals: Vec<Object>) -> Result<Object> {
+        let bytecode = Bytecode::read_file(path)?;
+        let mut vm = Vm::new();
+        vm.globals = globals.into_iter().map(Some).collect();
+        vm.run(bytecode)
+    }
+
+    /// Calls the global function `name`, or the builtin with that name.
+    pub fn call_function(&mut self, name: &str, args: Vec<Object>) -> Result<Object> {
+        let function = self
+            .get_global(name)
+            .or_else(|| BuiltInFn::from_name(name).map(Object::BuiltInFn))
+            .ok_or_else(|| anyhow!("Identifier {} not found", name))?;
+        self.program.call(&function, args)
+    }
+
+    /// Writes the syntax tree of every source evaluated from now on to `out`, as json.
+    pub fn dump_ast_to(&mut self, out: Rc<RefCell<dyn Write>>) {
+        self.program.dump_ast = Some(out);
+    }
+
+    /// Turns constant folding off, or back on. Results are the same either way.
+    pub fn set_optimize(&mut self, optimize: bool) {
+        self.program.optimize = optimize;
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Object> {
+        self.program.global(name)
+    }
+
+    pub fn set_global(&mut self, name: &str, value: Object) {
+        self.program.set_global(name, value)
+    }
+
+    /// Exposes `function` to scripts as the global `name`. Calls with a number of arguments
+    /// other than `arity` fail before reaching it, `None` accepts any number of them.
+    pub fn register_fn(
+        &mut self,
+        name: &str,
+        arity: Option<usize>,
+        function: impl Fn(&[Object]) -> Result<Object> + 'static,
+    ) {
+        let native = NativeFn::new(name, arity, function);
+        self.set_global(name, Object::NativeFn(Rc::new(native)));
+    }
+
+    /// Same as `register_fn`, for functions that need the interpreter running the script,
+    /// for instance to call back a function they were given.
+    pub fn register_fn_with_interpreter(
+        &mut self,
+        name: &str,
+        arity: Option<usize>,
+        function: impl Fn(&mut Interpreter, &[Object]) -> Result<Object> + 'static,
+    ) {
+        //a strong reference would keep the globals alive through the function stored in them
+        let program = self.program.downgrade();
+        self.register_fn(name, arity, move |args| {
+            let program = program
+                .upgrade()
+                .ok_or_else(|| anyhow!("the interpreter was dropped"))?;
+            function(&mut Interpreter { program }, args)
+        });
+    }
+}
+
+fn parse(source: &str) -> Result<Parser> {
+    let mut parser = Parser::new(Lexer::new(source));
+    if !parser.errors.is_empty() {
+        return Err(ParseError(mem::take(&mut parser.errors)).into());
+    }
+    Ok(parser)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::{Array, NativeObject};
+    use std::{
+        cell::Cell,
+        fmt::{self, Display},
+    };
+
+    #[test]
+    fn keep_globals_between_evaluations() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .eval_str("let double = fn(x) { x * 2 };")
+            .unwrap();
+        interpreter.eval_str("let four = double(2);").unwrap();
+
+        assert_eq!(interpreter.get_global("four"), Some(Object::Int(4)));
+        assert_eq!(interpreter.get_global("missing"), None);
+        assert_eq!(
+            interpreter
+                .call_function("double", vec![Object::Int(21)])
+                .unwrap(),
+            Object::Int(42)
+        );
+        let array = Object::Array(Array::new(vec![Object::Int(1), Object::Int(2)]));
+        assert_eq!(
+            interpreter.call_function("len", vec![array]).unwrap(),
+            Object::Int(2)
+        );
+    }
+
+    #[test]
+    fn set_globals_from_the_host() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_global("name", Object::String("monkey".into()));
+        interpreter.set_global("name", Object::String("gorilla".into()));
+
+        let result = interpreter.eval_str(r#"let greeting = "hi " + name; greeting"#);
+        assert_eq!(result.unwrap(), Object::String("hi gorilla".into()));
+        let result = interpreter.eval_str("let early = fn() { return 1; 2 }; early()");
+        assert_eq!(result.unwrap(), Object::Int(1));
+        assert_eq!(
+            interpreter.call_function("early", vec![]).unwrap(),
+            Object::Int(1)
+        );
+
+        interpreter
+            .eval_str("let base = 1; let add = fn(x) { base + x };")
+            .unwrap();
+        interpreter.set_global("base", Object::Int(10));
+        assert_eq!(
+            interpreter
+                .call_function("add", vec![Object::Int(5)])
+                .unwrap(),
+            Object::Int(15)
+        );
+    }
+
+    #[test]
+    fn report_errors() {
+        let mut interpreter = Interpreter::new();
+
+        let error = interpreter.eval_str("let = 1;").unwrap_err();
+        assert_eq!(error.to_string(), "Expected identifier but found: Assign");
+        assert!(error.downcast_ref::<ParseError>().is_some());
+        let error = interpreter.call_function("nope", vec![]).unwrap_err();
+        assert_eq!(error.to_string(), "Identifier nope not found");
+        interpreter.set_global("one", Object::Int(1));
+        let error = interpreter.call_function("one", vec![]).unwrap_err();
+        assert_eq!(error.to_string(), "expected a function, found: 1");
+        let error = interpreter.eval_file("does/not/exist.mk").unwrap_err();
+        assert_eq!(error.to_string(), "could not read does/not/exist.mk");
+    }
+
+    #[test]
+    fn report_arithmetic_errors() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .eval_str("let div = fn(x, y) { x / y }; let neg = fn(x) { -x };")
+            .unwrap();
+
+        let error = interpreter.eval_str("1 / 0").unwrap_err();
+        assert_eq!(error.to_string(), "division by zero");
+        let error = interpreter
+            .call_function("div", vec![Object::Int(1), Object::Int(0)])
+            .unwrap_err();
+        assert_eq!(error.to_string(), "division by zero");
+        let error = interpreter
+            .call_function("div", vec![Object::Int(i64::MIN), Object::Int(-1)])
+            .unwrap_err();
+        assert_eq!(error.to_string(), "integer overflow in operator `Div`");
+        let error = interpreter
+            .call_function("neg", vec![Object::Int(i64::MIN)])
+            .unwrap_err();
+        assert_eq!(error.to_string(), "integer overflow in operator `Minus`");
+        interpreter.set_global("max", Object::Int(i64::MAX));
+        let error = interpreter.eval_str("max + 1").unwrap_err();
+        assert_eq!(error.to_string(), "integer overflow in operator `Add`");
+        assert!(interpreter.eval_str("max * max").is_err());
+        assert_eq!(interpreter.eval_str("div(7, 2)").unwrap(), Object::Int(3));
+    }
+
+    #[test]
+    fn forget_lets_that_failed() {
+        let mut interpreter = Interpreter::new();
+        let error = interpreter.eval_str("let x = 1; let y = 1 + true; let z = 2;");
+        assert_eq!(
+            error.unwrap_err().to_string(),
+            "operator `Add` not supported between values \"1\" and \"true\""
+        );
+
+        assert_eq!(interpreter.get_global("x"), Some(Object::Int(1)));
+        let error = interpreter.eval_str("y").unwrap_err();
+        assert_eq!(error.to_string(), "Identifier y not found");
+        assert!(interpreter.eval_str("z").is_err());
+        let result = interpreter.eval_str("let y = 2; let z = 3; x + y + z");
+        assert_eq!(result.unwrap(), Object::Int(6));
+        assert!(interpreter.eval_str("let x = 4;").is_err());
+    }
+
+    #[test]
+    fn call_native_functions() {
+        let mut interpreter = Interpreter::new();
+        interpreter.register_fn("sum", None, |args| {
+            let ints = args.iter().map(|x| x.clone().into_int());
+            Ok(Object::Int(ints.sum::<Result<_, _>>()?))
+        });
+        interpreter.register_fn("fail", Some(0), |_| Err(anyhow!("host error")));
+
+        let result = interpreter.eval_str("let total = sum(1, 2, 3); sum(total, sum())");
+        assert_eq!(result.unwrap(), Object::Int(6));
+        assert_eq!(interpreter.eval_str("sum").unwrap().to_string(), "sum");
+        let error = interpreter.eval_str("fail(1)").unwrap_err();
+        assert_eq!(error.to_string(), "expected 0 argument, found: 1");
+        let error = interpreter.eval_str("fail()").unwrap_err();
+        assert_eq!(error.to_string(), "host error");
+    }
+
+    #[test]
+    fn native_functions_reach_the_interpreter() {
+        let mut interpreter = Interpreter::new();
+        interpreter.register_fn_with_interpreter("apply_twice", Some(2), |interpreter, args| {
+            let once = interpreter.call_function("apply", args.to_vec())?;
+            interpreter.call_function("apply", vec![args[0].clone(), once])
+        });
+        interpreter.register_fn_with_interpreter("store", Some(1), |interpreter, args| {
+            interpreter.set_global("stored", args[0].clone());
+            Ok(Object::Nil)
+        });
+
+        let input = "let apply = fn(f, x) { f(x) }; store(apply_twice(fn(x) { x * 3 }, 2))";
+        interpreter.eval_str(input).unwrap();
+        assert_eq!(interpreter.get_global("stored"), Some(Object::Int(18)));
+        assert_eq!(interpreter.eval_str("stored + 1").unwrap(), Object::Int(19));
+    }
+
+    struct Counter(Cell<i64>);
+
+    impl Display for Counter {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "Counter({})", self.0.get())
+        }
+    }
+
+    impl NativeObject for Counter {
+        fn type_name(&self) -> &'static str {
+            "counter"
+        }
+
+        fn get_property(&self, name: &str) -> Option<Object> {
+            (name == "count").then(|| Object::Int(self.0.get()))
+        }
+
+        fn call_method(&self, name: &str, args: &[Object]) -> Result<Object> {
+            match (name, args) {
+                ("add", [Object::Int(amount)]) => {
+                    self.0.set(self.0.get() + amount);
+                    Ok(Object::Int(self.0.get()))
+                }
+                _ => Err(anyhow!("counter has no method {}", name)),
+            }
+        }
+    }
+
+    #[test]
+    fn use_host_objects() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_global("counter", Object::Native(Rc::new(Counter(Cell::new(1)))));
+
+        let input = r#"counter["add"](2); let add = "add"; counter[add](3) + counter["count"]"#;
+        assert_eq!(interpreter.eval_str(input).unwrap(), Object::Int(12));
+        assert_eq!(
+            interpreter.eval_str(r#"counter["size"]"#).unwrap(),
+            Object::Nil
+        );
+        let counter = interpreter.get_global("counter").unwrap();
+        assert_eq!(counter.to_string(), "Counter(6)");
+        assert_eq!(counter.as_typeof(), "counter");
+        let error = interpreter.eval_str(r#"counter["reset"]()"#).unwrap_err();
+        assert_eq!(error.to_string(), "counter has no method reset");
+    }
+
+    #[test]
+    fn dump_the_ast() {
+        let out = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.dump_ast_to(out.clone());
+        assert_eq!(interpreter.eval_str("1 + 2").unwrap(), Object::Int(3));
+        let folded = String::from_utf8(mem::take(&mut *out.borrow_mut())).unwrap();
+        assert!(!folded.contains("BinaryExp"), "{}", folded);
+
+        interpreter.set_optimize(false);
+        assert_eq!(interpreter.eval_str("1 + 2").unwrap(), Object::Int(3));
+        let dump = String::from_utf8(mem::take(&mut *out.borrow_mut())).unwrap();
+        assert!(dump.contains("BinaryExp"), "{}", dump);
+        assert!(dump.ends_with("]\n"));
+    }
+
+    #[test]
+    fn eval_files() {
+        let path = std::env::temp_dir().join("monkey_interpreter_eval_files.mk");
+        fs::write(&path, "let answer = 6 * 7;\nanswer").unwrap();
+
+        let mut interpreter = Interpreter::new();
+        assert_eq!(interpreter.eval_file(&path).unwrap(), Object::Int(42));
+        assert_eq!(interpreter.get_global("answer"), Some(Object::Int(42)));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn run_compiled_files() {
+        let path = std::env::temp_dir().join("monkey_interpreter_run_compiled_files.mk");
+        let out = path.with_extension("mkc");
+        fs::write(&path, "let double = fn(x) { x * 2 };\ndouble(base)").unwrap();
+
+        Interpreter::compile_file(&path, &out, &["base"]).unwrap();
+        let result = Interpreter::run_compiled_file(&out, vec![Object::Int(21)]);
+        assert_eq!(result.unwrap(), Object::Int(42));
+        let error = Interpreter::compile_file(&path, &out, &[]).unwrap_err();
+        assert_eq!(error.root_cause().to_string(), "Identifier base not found");
+        fs::remove_file(path).unwrap();
+        fs::remove_file(out).unwrap();
+    }
+}
@@ -1,29 +1,153 @@
 use crate::token::{Identifier, Token};
+use serde::Serialize;
 use smol_str::SmolStr;
-use std::iter::{self, Peekable};
+use std::{
+    iter::{self, Peekable},
+    str::Chars,
+};
 
 #[derive(Debug)]
 pub struct Lexer {
     pub tokens: Vec<Token>,
+    //where each token is in the source, by index
+    pub spans: Vec<Span>,
+    pub comments: Vec<Comment>,
+    //indices of the tokens preceded by an empty line
+    pub blank_lines: Vec<usize>,
+}
+
+/// Location of a piece of source code. `start` and `end` are byte offsets, `line` and
+/// `column` start at 1 and point to its first character.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    /// Smallest span covering both `self` and `other`, which must come after it.
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            end: other.end,
+            ..self
+        }
+    }
+}
+
+/// A `//` comment, kept apart from the tokens so that the formatter can put it back.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comment {
+    //everything after the `//`, up to the end of the line
+    pub text: String,
+    //index of the token that follows the comment
+    pub token: usize,
+    //written at the end of a line, after some code
+    pub trailing: bool,
+    pub blank_line: bool,
+}
+
+//walks the characters of the source, keeping track of the position of the next one
+struct Cursor<'a> {
+    chars: Peekable<Chars<'a>>,
+    offset: usize,
+    line: usize,
+    column: usize,
+}
+
+impl Iterator for Cursor<'_> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        let char = self.chars.next()?;
+        self.offset += char.len_utf8();
+        if char == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(char)
+    }
+}
+
+impl Cursor<'_> {
+    fn next_if(&mut self, func: impl FnOnce(&char) -> bool) -> Option<char> {
+        if func(self.chars.peek()?) {
+            self.next()
+        } else {
+            None
+        }
+    }
+
+    fn next_if_eq(&mut self, expected: &char) -> Option<char> {
+        self.next_if(|x| x == expected)
+    }
 }
 
 impl Lexer {
     pub fn new(input: &str) -> Self {
-        let mut chars = input.trim().chars().filter(|x| x.is_ascii()).peekable();
+        let trimmed = input.trim();
+        let skipped = &input[..input.len() - input.trim_start().len()];
+        let mut chars = Cursor {
+            chars: trimmed.chars().peekable(),
+            offset: skipped.len(),
+            line: skipped.matches('\n').count() + 1,
+            column: skipped.rsplit('\n').next().map_or(0, |x| x.chars().count()) + 1,
+        };
         let mut tokens = Vec::with_capacity(32);
+        let mut spans = Vec::with_capacity(32);
+        let mut comments = Vec::new();
+        let mut blank_lines = Vec::new();
+        let mut newlines = 0;
 
-        while let Some(char) = chars.next() {
-            if char.is_whitespace() {
+        loop {
+            let (start, line, column) = (chars.offset, chars.line, chars.column);
+            let Some(char) = chars.next() else {
+                break;
+            };
+            if char == '\n' {
+                newlines += 1;
+                continue;
+            }
+            if char.is_whitespace() || !char.is_ascii() {
+                continue;
+            }
+            if char == '/' && chars.next_if_eq(&'/').is_some() {
+                let text: String = iter::from_fn(|| chars.next_if(|x| *x != '\n')).collect();
+                comments.push(Comment {
+                    text: text.trim_end().to_string(),
+                    token: tokens.len(),
+                    trailing: newlines == 0 && !tokens.is_empty(),
+                    blank_line: newlines > 1,
+                });
+                newlines = 0;
                 continue;
             }
+            if newlines > 1 {
+                blank_lines.push(tokens.len());
+            }
+            newlines = 0;
             let token = Lexer::new_helper(char, &mut chars);
             tokens.push(token);
+            spans.push(Span {
+                start,
+                end: chars.offset,
+                line,
+                column,
+            });
         }
 
-        Lexer { tokens }
+        Lexer {
+            tokens,
+            spans,
+            comments,
+            blank_lines,
+        }
     }
 
-    fn new_helper(char: char, chars: &mut Peekable<impl Iterator<Item = char>>) -> Token {
+    fn new_helper(char: char, chars: &mut Cursor) -> Token {
         match char {
             '+' => Token::Plus,
             ',' => Token::Comma,
@@ -63,7 +187,7 @@ impl Lexer {
                     "else" => Token::Else,
                     "return" => Token::Return,
                     "nil" => Token::Nil,
-                    _ => Token::Identifier(Identifier::new(keyword)),
+                    _ => Token::Identifier(Identifier::new(&keyword)),
                 }
             }
             _ if char.is_ascii_digit() => iter::once(char)
@@ -113,4 +237,75 @@ mod tests {
         let result = Lexer::new(input);
         assert_yaml_snapshot!(result.tokens);
     }
+
+    #[test]
+    fn tokenize_comments() {
+        let input = "
+        // leading
+        let a = 1; // trailing
+
+        // after a blank line
+        a / 2
+        ";
+
+        let result = Lexer::new(input);
+        assert_eq!(result.tokens.len(), 8);
+        assert_eq!(result.blank_lines, Vec::<usize>::new());
+        assert_eq!(
+            result.comments,
+            vec![
+                Comment {
+                    text: " leading".into(),
+                    token: 0,
+                    trailing: false,
+                    blank_line: false,
+                },
+                Comment {
+                    text: " trailing".into(),
+                    token: 5,
+                    trailing: true,
+                    blank_line: false,
+                },
+                Comment {
+                    text: " after a blank line".into(),
+                    token: 5,
+                    trailing: false,
+                    blank_line: true,
+                },
+            ]
+        );
+        assert_eq!(Lexer::new("a\n\n\nb").blank_lines, vec![1]);
+    }
+
+    #[test]
+    fn track_token_spans() {
+        let input = "\n  let abc = \"hi\";\n  abc <= 10 // done\n";
+
+        let result = Lexer::new(input);
+        let spans: Vec<_> = result
+            .spans
+            .iter()
+            .map(|x| (&input[x.start..x.end], x.line, x.column))
+            .collect();
+        assert_eq!(
+            spans,
+            [
+                ("let", 2, 3),
+                ("abc", 2, 7),
+                ("=", 2, 11),
+                ("\"hi\"", 2, 13),
+                (";", 2, 17),
+                ("abc", 3, 3),
+                ("<=", 3, 7),
+                ("10", 3, 10),
+            ]
+        );
+        assert_eq!(result.spans[0].to(result.spans[4]).end, 18);
+    }
+
+    #[test]
+    fn tokenize_non_ascii_strings() {
+        let result = Lexer::new("\u{feff}\"héllo wörld\"");
+        assert_eq!(result.tokens, [Token::String("héllo wörld".into())]);
+    }
 }
@@ -1,9 +1,44 @@
-#![allow(dead_code, unused_variables)]
+//! A Monkey interpreter. `Interpreter` is the entry point for embedding it, while `lexer`,
+//! `parser`, `ast`, `token` and `visitor` let tools work with the code itself. Hidden modules
+//! are public for the binaries and the bytecode backend, they aren't a stable API.
 
-pub(crate) mod ast;
+#![allow(
+    dead_code,
+    unused_variables,
+    clippy::should_implement_trait,
+    clippy::mutable_key_type
+)]
+
+pub mod ast;
+#[doc(hidden)]
+pub mod bytecode;
+#[doc(hidden)]
+pub mod code;
+#[doc(hidden)]
+pub mod compiler;
+pub mod convert;
 pub mod eval;
+#[doc(hidden)]
+pub mod formatter;
+pub(crate) mod gc;
+pub(crate) mod interner;
+pub mod interpreter;
 pub mod lexer;
+#[doc(hidden)]
+pub mod lint;
 pub mod object;
+pub(crate) mod optimizer;
 pub mod parser;
-pub(crate) mod token;
+pub(crate) mod printer;
+pub(crate) mod resolver;
+#[doc(hidden)]
+pub mod symbols;
+pub mod token;
 pub(crate) mod token_parser;
+pub mod visitor;
+#[doc(hidden)]
+pub mod vm;
+
+pub use convert::{FromObject, IntoObject};
+pub use interpreter::Interpreter;
+pub use object::Object;
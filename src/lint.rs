@@ -0,0 +1,304 @@
+use std::fmt::{self, Display};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{
+    ast::{
+        BinaryExpression, BinaryOperator, BlockStatement, CallExpression, Expression, Literal,
+        Statement, UnaryOperator,
+    },
+    interpreter::ParseError,
+    lexer::{Lexer, Span},
+    object::BuiltInFn,
+    parser::{NodeSpans, Parser},
+    symbols::{DefinitionKind, Symbols},
+    visitor::{walk_call, walk_expression, Visitor},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LintKind {
+    Unused,
+    Shadowed,
+    Unreachable,
+    Arity,
+    AlwaysFalse,
+}
+
+impl LintKind {
+    pub const fn name(&self) -> &'static str {
+        match self {
+            LintKind::Unused => "unused",
+            LintKind::Shadowed => "shadowed",
+            LintKind::Unreachable => "unreachable",
+            LintKind::Arity => "arity",
+            LintKind::AlwaysFalse => "always-false",
+        }
+    }
+}
+
+/// Code that runs but is most likely a mistake.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Lint {
+    pub kind: LintKind,
+    pub span: Span,
+    pub message: String,
+}
+
+impl Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Span { line, column, .. } = self.span;
+        write!(
+            f,
+            "{}:{}: {} [{}]",
+            line,
+            column,
+            self.message,
+            self.kind.name()
+        )
+    }
+}
+
+/// Lints of a source, in the order they appear in it. Fails with a `ParseError` when the
+/// source doesn't parse.
+pub fn lint(source: &str) -> Result<Vec<Lint>> {
+    let lexer = Lexer::new(source);
+    let symbols = Symbols::new(&lexer);
+    let parser = Parser::new(lexer);
+    if !parser.errors.is_empty() {
+        return Err(ParseError(parser.errors).into());
+    }
+
+    let mut linter = Linter {
+        lints: definition_lints(&symbols),
+        symbols,
+        spans: &parser.node_spans,
+    };
+    linter.visit_program(&parser.nodes);
+
+    let mut lints = linter.lints;
+    lints.sort_by_key(|x| x.span.start);
+    Ok(lints)
+}
+
+fn definition_lints(symbols: &Symbols) -> Vec<Lint> {
+    let mut lints = Vec::new();
+    for (index, definition) in symbols.definitions.iter().enumerate() {
+        let name = definition.name;
+        let lint = |kind, message| Lint {
+            kind,
+            span: definition.span,
+            message,
+        };
+        //names starting with `_` are unused on purpose, like a callback parameter
+        if !name.as_str().starts_with('_') && symbols.references_to(index).next().is_none() {
+            let kind = match definition.kind {
+                DefinitionKind::Let => "variable",
+                DefinitionKind::Parameter => "parameter",
+            };
+            lints.push(lint(LintKind::Unused, format!("unused {} {}", kind, name)));
+        }
+        if let Some(outer) = symbols.shadowed(index) {
+            let line = symbols.definitions[outer].span.line;
+            let message = format!("{} shadows the one defined on line {}", name, line);
+            lints.push(lint(LintKind::Shadowed, message));
+        } else if BuiltInFn::from_name(name.as_str()).is_some() {
+            let message = format!("{} shadows the builtin of the same name", name);
+            lints.push(lint(LintKind::Shadowed, message));
+        }
+    }
+    lints
+}
+
+//a node the parser didn't record a span for, which it does for all of them, isn't reported
+struct Linter<'a> {
+    symbols: Symbols,
+    spans: &'a NodeSpans,
+    lints: Vec<Lint>,
+}
+
+impl Linter<'_> {
+    //only the first statement after a `return` is reported, the others obviously follow
+    fn visit_statements(&mut self, statements: &[Statement]) {
+        let after_return = statements
+            .iter()
+            .skip_while(|x| !matches!(x, Statement::Return(_)))
+            .nth(1);
+        if let Some(span) = after_return.and_then(|x| self.spans.statement(x)) {
+            self.lints.push(Lint {
+                kind: LintKind::Unreachable,
+                span,
+                message: "unreachable statement after return".into(),
+            });
+        }
+        statements.iter().for_each(|x| self.visit_statement(x));
+    }
+
+    fn check_arity(&mut self, expression: &CallExpression) {
+        let function = expression.function.as_ref();
+        let Expression::Identifier(_) = function else {
+            return;
+        };
+        let span = self.spans.expression(function);
+        let Some(reference) = span.and_then(|x| self.symbols.reference_at(x.start)) else {
+            return;
+        };
+        let builtin = match reference.definition {
+            None => BuiltInFn::from_name(reference.name.as_str()),
+            Some(_) => None,
+        };
+        let Some((builtin, arity)) = builtin.and_then(|x| Some((x, x.arity()?))) else {
+            return;
+        };
+        let given = expression.arguments.len();
+        if given != arity {
+            let plural = if arity == 1 { "" } else { "s" };
+            self.lints.push(Lint {
+                kind: LintKind::Arity,
+                span: reference.span,
+                message: format!(
+                    "{} takes {} argument{} but was given {}",
+                    builtin.name(),
+                    arity,
+                    plural,
+                    given
+                ),
+            });
+        }
+    }
+
+    fn check_comparison(&mut self, expression: &Expression, binary: &BinaryExpression) {
+        if !is_comparison(&binary.operator) {
+            return;
+        }
+        let Some(span) = self.spans.expression(expression) else {
+            return;
+        };
+        if let Some(reason) = never_true(binary) {
+            self.lints.push(Lint {
+                kind: LintKind::AlwaysFalse,
+                span,
+                message: format!("`{}` is always false, {}", expression, reason),
+            });
+        }
+    }
+}
+
+impl Visitor for Linter<'_> {
+    fn visit_program(&mut self, statements: &[Statement]) {
+        self.visit_statements(statements);
+    }
+
+    fn visit_block(&mut self, block: &BlockStatement) {
+        self.visit_statements(&block.0);
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        if let Expression::BinaryExp(binary) = expression {
+            self.check_comparison(expression, binary);
+        }
+        walk_expression(self, expression);
+    }
+
+    fn visit_call(&mut self, expression: &CallExpression) {
+        self.check_arity(expression);
+        walk_call(self, expression);
+    }
+}
+
+fn is_comparison(operator: &BinaryOperator) -> bool {
+    use BinaryOperator::*;
+    matches!(operator, Eq | NotEq | Lt | Lte | Gt | Gte)
+}
+
+//`Object::lt` and the like are false unless both sides are ints, and values of different
+//types are never equal
+fn never_true(expression: &BinaryExpression) -> Option<String> {
+    let lhs = static_type(&expression.lhs);
+    let rhs = static_type(&expression.rhs);
+    match expression.operator {
+        BinaryOperator::Lt | BinaryOperator::Lte | BinaryOperator::Gt | BinaryOperator::Gte => {
+            let other = lhs.into_iter().chain(rhs).find(|x| *x != "int")?;
+            Some(format!("{} values can't be ordered", other))
+        }
+        BinaryOperator::Eq => {
+            let (lhs, rhs) = (lhs?, rhs?);
+            (lhs != rhs).then(|| format!("{} and {} values are never equal", lhs, rhs))
+        }
+        _ => None,
+    }
+}
+
+//type of what an expression evaluates to, when it is known without running it
+fn static_type(expression: &Expression) -> Option<&'static str> {
+    Some(match expression {
+        Expression::Literal(literal) => match literal {
+            Literal::Int(_) => "int",
+            Literal::String(_) => "string",
+            Literal::Array(_) => "array",
+            Literal::Hash(_) => "object",
+            Literal::True | Literal::False => "bool",
+            Literal::Nil => "nil",
+        },
+        Expression::Function(_) => "function",
+        Expression::UnaryExpression(exp) => match exp.operator {
+            UnaryOperator::Not => "bool",
+            UnaryOperator::Minus => "int",
+        },
+        Expression::BinaryExp(exp) => match exp.operator {
+            BinaryOperator::Add => match (static_type(&exp.lhs)?, static_type(&exp.rhs)?) {
+                ("int", "int") => "int",
+                ("string", "string") => "string",
+                _ => return None,
+            },
+            BinaryOperator::Sub | BinaryOperator::Mul | BinaryOperator::Div => "int",
+            _ => "bool",
+        },
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use insta::assert_yaml_snapshot;
+
+    fn messages(input: &str) -> Vec<String> {
+        lint(input).unwrap().iter().map(|x| x.to_string()).collect()
+    }
+
+    #[test]
+    fn report_lints() {
+        let input = r#"let unused = 1;
+let len = fn(x, _index) { x };
+let check = fn(a, b) {
+    let f = fn(a) { a + b };
+    if ("a" < 1) { return f(a); puts(a); len(b) }
+    return [first(a, b), push(a)];
+    a == "a" + "b";
+};
+check(1 == true, 2 != "2", -1 >= 1 - 1, !true > 0);
+"#;
+        assert_yaml_snapshot!(messages(input));
+    }
+
+    #[test]
+    fn accept_correct_programs() {
+        let input = r#"let map = fn(arr, f) {
+    let iter = fn(items, acc) {
+        if (len(items) == 0) {
+            return acc;
+        }
+        iter(rest(items), push(acc, f(first(items))))
+    };
+    iter(arr, [])
+};
+let names = {"a": 1, "b": map([1, 2], fn(_x) { 3 })};
+puts(names["a"] < len("abc"), last(names["b"]), "x" == "y")
+"#;
+        assert_eq!(messages(input), Vec::<String>::new());
+        let error = lint("let = 1").unwrap_err();
+        assert!(error.downcast_ref::<ParseError>().is_some());
+    }
+}
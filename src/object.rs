@@ -1,18 +1,22 @@
 use std::{
     cell::RefCell,
-    collections::{hash_map::Entry, HashMap},
     fmt::{self, Display},
+    iter,
     ops::{Deref, DerefMut},
     ptr,
     rc::Rc,
 };
 
-use serde::{self, Serialize};
+use fnv::FnvHashMap;
+use serde::{self, ser::SerializeStruct, Serialize, Serializer};
 use smol_str::SmolStr;
 use thiserror::Error;
 
 use crate::{
     ast::{BinaryOperator, BlockStatement, UnaryOperator},
+    compiler::CompiledFunction,
+    gc::Heap,
+    printer::function_to_string,
     token::Identifier,
 };
 
@@ -25,7 +29,12 @@ pub enum Object {
     Array(Array),
     HashTable(Box<HashTable>),
     String(SmolStr),
-    Function(Box<Function>),
+    Function(Rc<Function>),
+    #[serde(rename = "Function")]
+    Closure(Rc<Closure>),
+    NativeFn(Rc<NativeFn>),
+    #[serde(serialize_with = "serialize_native")]
+    Native(Rc<dyn NativeObject>),
     Return(Box<Object>),
 }
 
@@ -39,7 +48,10 @@ impl std::hash::Hash for Object {
             Object::Array(array) => array.as_ptr().hash(state),
             Object::HashTable(hashtable) => ptr::addr_of!(*hashtable).hash(state),
             Object::String(string) => string.hash(state),
-            Object::Function(function) => ptr::addr_of!(*function).hash(state),
+            Object::Function(function) => Rc::as_ptr(function).hash(state),
+            Object::Closure(closure) => Rc::as_ptr(closure).hash(state),
+            Object::NativeFn(native) => Rc::as_ptr(native).hash(state),
+            Object::Native(native) => Rc::as_ptr(native).cast::<()>().hash(state),
             Object::Return(_) => state.write_u16(1337),
         }
     }
@@ -57,14 +69,17 @@ impl PartialEq for Object {
             (Self::BuiltInFn(lhs), Self::BuiltInFn(rhs)) => lhs == rhs,
             (Self::Array(lhs), Self::Array(rhs)) => ptr::eq(lhs, rhs),
             (Self::HashTable(lhs), Self::HashTable(rhs)) => ptr::eq(lhs, rhs),
-            (Self::Function(lhs), Self::Function(rhs)) => ptr::eq(lhs, rhs),
+            (Self::Function(lhs), Self::Function(rhs)) => Rc::ptr_eq(lhs, rhs),
+            (Self::Closure(lhs), Self::Closure(rhs)) => Rc::ptr_eq(lhs, rhs),
+            (Self::NativeFn(lhs), Self::NativeFn(rhs)) => Rc::ptr_eq(lhs, rhs),
+            (Self::Native(lhs), Self::Native(rhs)) => Rc::ptr_eq(lhs, rhs),
             (Self::Return(lhs), Self::Return(rhs)) => false,
             _ => false,
         }
     }
 }
 
-#[derive(Debug, Serialize, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum BuiltInFn {
     Len,
     First,
@@ -74,18 +89,109 @@ pub enum BuiltInFn {
     Puts,
 }
 
+type NativeFunction = dyn Fn(&[Object]) -> anyhow::Result<Object>;
+
+/// A function implemented in Rust by the host, see `Interpreter::register_fn`.
+pub struct NativeFn {
+    pub name: SmolStr,
+    //`None` accepts any number of arguments
+    pub arity: Option<usize>,
+    function: Box<NativeFunction>,
+}
+
+impl NativeFn {
+    pub fn new(
+        name: &str,
+        arity: Option<usize>,
+        function: impl Fn(&[Object]) -> anyhow::Result<Object> + 'static,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            arity,
+            function: Box::new(function),
+        }
+    }
+
+    pub fn call(&self, arguments: &[Object]) -> anyhow::Result<Object> {
+        match self.arity {
+            Some(expected) if expected != arguments.len() => Err(EvalError::ArgumentCountError {
+                expected,
+                found: arguments.len(),
+            })?,
+            _ => (self.function)(arguments),
+        }
+    }
+}
+
+impl fmt::Debug for NativeFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NativeFn")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Serialize for NativeFn {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.name)
+    }
+}
+
+/// An opaque value the host hands to scripts, such as a connection or a request.
+///
+/// Scripts read its properties with `value["name"]` and call its methods with
+/// `value["name"](arguments)`.
+pub trait NativeObject: Display {
+    fn type_name(&self) -> &'static str;
+
+    /// `None` when there is no such property, which scripts see as `nil`.
+    fn get_property(&self, name: &str) -> Option<Object> {
+        None
+    }
+
+    fn call_method(&self, name: &str, arguments: &[Object]) -> anyhow::Result<Object> {
+        Err(anyhow::anyhow!(
+            "{} has no method {}",
+            self.type_name(),
+            name
+        ))
+    }
+}
+
+impl fmt::Debug for dyn NativeObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}({})", self.type_name(), self)
+    }
+}
+
+fn serialize_native<S: Serializer>(
+    native: &Rc<dyn NativeObject>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_str(&native.to_string())
+}
+
 #[derive(Debug, Serialize, Clone)]
 pub struct Array(pub Vec<Object>);
 
-pub struct HashTableKey(Object);
-
 #[derive(Debug, Serialize, Clone)]
-pub struct HashTable(pub HashMap<Object, Object>);
+pub struct HashTable(pub FnvHashMap<Object, Object>);
 
 impl HashTable {
-    pub fn new(storage: HashMap<Object, Object>) -> Self {
+    pub fn new(storage: FnvHashMap<Object, Object>) -> Self {
         Self(storage)
     }
+
+    pub fn get(&self, key: Object) -> Option<&Object> {
+        self.0.get(&key)
+    }
+}
+
+impl FromIterator<(Object, Object)> for HashTable {
+    fn from_iter<T: IntoIterator<Item = (Object, Object)>>(iter: T) -> Self {
+        Self(iter.into_iter().collect())
+    }
 }
 
 impl From<Array> for Object {
@@ -119,6 +225,73 @@ impl DerefMut for Array {
 }
 
 impl BuiltInFn {
+    pub const ALL: [BuiltInFn; 6] = [
+        BuiltInFn::Len,
+        BuiltInFn::First,
+        BuiltInFn::Last,
+        BuiltInFn::Rest,
+        BuiltInFn::Push,
+        BuiltInFn::Puts,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|x| x.name() == name)
+    }
+
+    pub fn call(self, arguments: Vec<Object>) -> Result<Object> {
+        match self {
+            BuiltInFn::Len => match TryInto::<[Object; 1]>::try_into(arguments) {
+                Ok([val]) => Ok(match val {
+                    Object::String(val) => Object::Int(val.len() as i64),
+                    Object::Array(val) => Object::Int(val.len() as i64),
+                    val => return Err(EvalError::argument_type("array or string", val)),
+                }),
+                Err(vec) => Err(EvalError::argument_count(1, vec)),
+            },
+            BuiltInFn::First => match TryInto::<[Object; 1]>::try_into(arguments) {
+                Ok([val]) => Ok(match val {
+                    Object::Array(val) => val.first().cloned().unwrap_or(NIL),
+                    val => return Err(EvalError::argument_type("array", val)),
+                }),
+                Err(vec) => Err(EvalError::argument_count(1, vec)),
+            },
+            BuiltInFn::Last => match TryInto::<[Object; 1]>::try_into(arguments) {
+                Ok([val]) => Ok(match val {
+                    Object::Array(val) => val.last().cloned().unwrap_or(NIL),
+                    val => return Err(EvalError::argument_type("array", val)),
+                }),
+                Err(vec) => Err(EvalError::argument_count(1, vec)),
+            },
+            BuiltInFn::Rest => match TryInto::<[Object; 1]>::try_into(arguments) {
+                Ok([val]) => Ok(match val {
+                    Object::Array(val) => Array::new(val.0.into_iter().skip(1).collect()).into(),
+                    val => return Err(EvalError::argument_type("array", val)),
+                }),
+                Err(vec) => Err(EvalError::argument_count(1, vec)),
+            },
+            BuiltInFn::Push => match TryInto::<[Object; 2]>::try_into(arguments) {
+                Ok([container, element]) => Ok(match (container, element) {
+                    (Object::Array(array), element) => {
+                        Array::new(array.0.into_iter().chain(iter::once(element)).collect()).into()
+                    }
+                    (container, element) => {
+                        return Err(EvalError::ArgumentTypeError {
+                            expected: "array and element",
+                            found: format!("{} and {}", container, element),
+                        })
+                    }
+                }),
+                Err(vec) => Err(EvalError::argument_count(2, vec)),
+            },
+            BuiltInFn::Puts => {
+                for arg in arguments {
+                    println!("{}", arg)
+                }
+                Ok(NIL)
+            }
+        }
+    }
+
     pub const fn name(&self) -> &'static str {
         match self {
             BuiltInFn::Len => "len",
@@ -129,10 +302,63 @@ impl BuiltInFn {
             BuiltInFn::Puts => "puts",
         }
     }
+
+    /// Number of arguments it takes, `None` when it accepts any number of them.
+    pub const fn arity(&self) -> Option<usize> {
+        match self {
+            BuiltInFn::Len | BuiltInFn::First | BuiltInFn::Last | BuiltInFn::Rest => Some(1),
+            BuiltInFn::Push => Some(2),
+            BuiltInFn::Puts => None,
+        }
+    }
+
+    /// What it does, in a sentence.
+    pub const fn doc(&self) -> &'static str {
+        match self {
+            BuiltInFn::Len => {
+                "Returns the number of characters of a string or the number of elements of an array."
+            }
+            BuiltInFn::First => "Returns the first element of an array, or nil when it is empty.",
+            BuiltInFn::Last => "Returns the last element of an array, or nil when it is empty.",
+            BuiltInFn::Rest => "Returns a new array with every element but the first one.",
+            BuiltInFn::Push => {
+                "Returns a new array with the element appended, leaving the original one unchanged."
+            }
+            BuiltInFn::Puts => "Prints each value on its own line and returns nil.",
+        }
+    }
+
+    /// How it is called, with the types it accepts and returns.
+    pub const fn signature(&self) -> &'static str {
+        match self {
+            BuiltInFn::Len => "len(string | array) -> int",
+            BuiltInFn::First => "first(array) -> element",
+            BuiltInFn::Last => "last(array) -> element",
+            BuiltInFn::Rest => "rest(array) -> array",
+            BuiltInFn::Push => "push(array, element) -> array",
+            BuiltInFn::Puts => "puts(values...) -> nil",
+        }
+    }
 }
 
 type Result<T> = std::result::Result<T, EvalError>;
 
+impl EvalError {
+    fn argument_count(expected: usize, found: Vec<Object>) -> Self {
+        Self::ArgumentCountError {
+            expected,
+            found: found.len(),
+        }
+    }
+
+    fn argument_type(expected: &'static str, found: Object) -> Self {
+        Self::ArgumentTypeError {
+            expected,
+            found: found.to_string(),
+        }
+    }
+}
+
 pub const TRUE: Object = Object::Bool(true);
 pub const FALSE: Object = Object::Bool(false);
 pub const NIL: Object = Object::Nil;
@@ -156,14 +382,29 @@ pub enum EvalError {
     CoercionError { target: &'static str, value: String },
     #[error("Identifier {0} already defined")]
     IdentifierAlreadyDefined(Identifier),
+    #[error("index operator not supported: {container}[{index}]")]
+    IndexError { container: String, index: String },
+    #[error("expected {expected} argument, found: {found}")]
+    ArgumentCountError { expected: usize, found: usize },
+    #[error("division by zero")]
+    DivisionByZero,
+    #[error("integer overflow in operator `{0}`")]
+    Overflow(&'static str),
+    #[error("expected {expected}, found: {found}")]
+    ArgumentTypeError {
+        expected: &'static str,
+        found: String,
+    },
 }
 
 #[derive(Serialize, Clone, Default)]
 pub struct Environment {
-    //maybe use a single hashmap with the function name as key
-    curr: HashMap<SmolStr, Object>,
+    //indexed by the slots the resolver assigned to each `let` and parameter
+    pub(crate) curr: Vec<Option<Object>>,
+    #[serde(skip_serializing)]
+    pub(crate) outer: Option<SharedEnv>,
     #[serde(skip_serializing)]
-    outer: Option<SharedEnv>,
+    pub(crate) heap: Heap,
 }
 
 impl fmt::Debug for Environment {
@@ -179,50 +420,55 @@ pub type SharedEnv = Rc<RefCell<Box<Environment>>>;
 impl Environment {
     pub fn new() -> Self {
         Self {
-            curr: HashMap::new(),
+            curr: Vec::new(),
             outer: None,
+            heap: Heap::new(),
         }
     }
 
-    pub fn new_enclosed(outer: SharedEnv, curr: HashMap<SmolStr, Object>) -> SharedEnv {
-        Rc::new(RefCell::new(Box::new(Self {
-            curr,
+    /// A global environment, tracked by a new heap.
+    pub fn new_shared() -> SharedEnv {
+        let env = Self::new();
+        env.heap.clone().alloc(env)
+    }
+
+    pub fn new_enclosed(outer: SharedEnv, args: Vec<Object>) -> SharedEnv {
+        let heap = outer.borrow().heap.clone();
+        heap.alloc(Self {
+            curr: args.into_iter().map(Some).collect(),
             outer: Some(outer),
-        })))
-    }
-
-    pub fn get(&self, name: &Identifier) -> Option<Object> {
-        self.curr
-            .get(&name.inner())
-            .cloned()
-            .or_else(|| self.outer.as_ref().and_then(|x| x.borrow().get(name)))
-            .or_else(|| match name.inner().as_str() {
-                //TODO: find some way to detect when there is a case missing here
-                "len" => Some(Object::BuiltInFn(BuiltInFn::Len)),
-                "first" => Some(Object::BuiltInFn(BuiltInFn::First)),
-                "last" => Some(Object::BuiltInFn(BuiltInFn::Last)),
-                "rest" => Some(Object::BuiltInFn(BuiltInFn::Rest)),
-                "push" => Some(Object::BuiltInFn(BuiltInFn::Push)),
-                "puts" => Some(Object::BuiltInFn(BuiltInFn::Puts)),
-                _ => None,
-            })
-    }
-
-    pub fn try_insert(&mut self, ident: &Identifier, value: Object) -> Result<()> {
-        match self.curr.entry(ident.inner()) {
-            Entry::Occupied(_) => Err(EvalError::IdentifierAlreadyDefined(ident.clone())),
-            Entry::Vacant(entry) => {
-                entry.insert(value);
-                Ok(())
-            }
+            heap: heap.clone(),
+        })
+    }
+
+    pub fn heap(&self) -> &Heap {
+        &self.heap
+    }
+
+    /// Looks up `slot` in the environment `depth` levels up, `None` if it wasn't set yet.
+    pub fn get(&self, depth: usize, slot: usize) -> Option<Object> {
+        match depth {
+            0 => self.curr.get(slot).cloned().flatten(),
+            _ => self.outer.as_ref()?.borrow().get(depth - 1, slot),
         }
     }
+
+    pub fn set(&mut self, slot: usize, value: Object) {
+        if slot >= self.curr.len() {
+            self.curr.resize(slot + 1, None);
+        }
+        self.curr[slot] = Some(value);
+    }
 }
 
 #[derive(Serialize, Clone)]
 pub struct Function {
     pub parameters: Vec<Identifier>,
-    pub body: BlockStatement,
+    #[serde(skip_serializing)]
+    pub body: Rc<BlockStatement>,
+    //the body as written, which differs from `body` once the optimizer folded it
+    #[serde(rename = "body")]
+    pub source: Rc<BlockStatement>,
     #[serde(skip_serializing)]
     pub env: SharedEnv,
 }
@@ -238,13 +484,41 @@ impl fmt::Debug for Function {
 
 impl Display for Function {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let params = self
-            .parameters
-            .iter()
-            .map(|x| x.inner())
-            .collect::<Vec<_>>()
-            .join(", ");
-        write!(f, "fn({}) {{todo()!}}", params)
+        f.write_str(&function_to_string(&self.parameters, &self.source))
+    }
+}
+
+/// A function compiled to bytecode, together with the free variables it captured.
+#[derive(Debug, Clone)]
+pub struct Closure {
+    pub function: Rc<CompiledFunction>,
+    pub free: Vec<Object>,
+}
+
+impl Closure {
+    pub fn new(function: Rc<CompiledFunction>, free: Vec<Object>) -> Self {
+        Self { function, free }
+    }
+}
+
+//serialized exactly like `Function` so both backends produce the same snapshots, functions
+//loaded from a `.mkc` file have no source and serialize `parameters` and `body` as nil
+impl Serialize for Closure {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let source = self.function.source.as_ref();
+        let mut state = serializer.serialize_struct("Function", 2)?;
+        state.serialize_field("parameters", &source.map(|x| &x.parameters))?;
+        state.serialize_field("body", &source.map(|x| &x.body))?;
+        state.end()
+    }
+}
+
+impl Display for Closure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.function.source {
+            Some(source) => f.write_str(&function_to_string(&source.parameters, &source.body)),
+            None => write!(f, "fn/{}", self.function.num_parameters),
+        }
     }
 }
 
@@ -257,6 +531,9 @@ impl Display for Object {
             Object::String(str) => write!(f, "{}", str),
             Object::BuiltInFn(builtin) => write!(f, "{}", builtin.name()),
             Object::Function(function) => write!(f, "{}", function),
+            Object::Closure(closure) => write!(f, "{}", closure),
+            Object::NativeFn(native) => write!(f, "{}", native.name),
+            Object::Native(native) => write!(f, "{}", native),
             Object::Return(obj) => write!(f, "{}", obj),
             Object::HashTable(hash) => write!(
                 f,
@@ -281,10 +558,146 @@ impl Display for Object {
     }
 }
 
+//how much of a collection `Object::inspect` shows before cutting it short
+const INSPECT_ITEMS: usize = 32;
+const INSPECT_DEPTH: usize = 6;
+
+enum Entry<'a> {
+    Value(&'a Object),
+    Pair(&'a Object, &'a Object),
+    More(usize),
+}
+
+impl Object {
+    /// Renders the value as Monkey code, for the REPL. Unlike `Display`, strings are quoted,
+    /// collections that don't fit in `width` columns are spread over several lines, and long
+    /// or deeply nested ones are cut short.
+    pub fn inspect(&self, width: usize) -> String {
+        let mut out = String::new();
+        self.inspect_into(&mut out, width, 0, 0);
+        out
+    }
+
+    //lines after the first one are indented by `indent`
+    fn inspect_into(&self, out: &mut String, width: usize, indent: usize, depth: usize) {
+        let start = out.len();
+        let column = start - out.rfind('\n').map_or(0, |x| x + 1);
+        if self.inspect_flat(out, start + width.saturating_sub(column), depth) {
+            return;
+        }
+        out.truncate(start);
+        let Some((open, close, entries)) = self.entries(depth) else {
+            //a function body, the only other value that spans several lines
+            self.inspect_flat(out, usize::MAX, depth);
+            let text = out.split_off(start);
+            out.push_str(&text.replace('\n', &format!("\n{}", " ".repeat(indent))));
+            return;
+        };
+
+        out.push(open);
+        for entry in entries {
+            out.push('\n');
+            out.push_str(&" ".repeat(indent + 4));
+            match entry {
+                Entry::Value(value) => value.inspect_into(out, width, indent + 4, depth + 1),
+                Entry::Pair(key, value) => {
+                    key.inspect_flat(out, usize::MAX, depth + 1);
+                    out.push_str(": ");
+                    value.inspect_into(out, width, indent + 4, depth + 1);
+                }
+                Entry::More(count) => {
+                    out.push_str(&format!("...{} more", count));
+                    continue;
+                }
+            }
+            out.push(',');
+        }
+        out.push('\n');
+        out.push_str(&" ".repeat(indent));
+        out.push(close);
+    }
+
+    //gives up as soon as `out` grows past `limit`
+    fn inspect_flat(&self, out: &mut String, limit: usize, depth: usize) -> bool {
+        match self {
+            Object::String(string) => out.push_str(&format!("{:?}", string.as_str())),
+            Object::Return(value) => return value.inspect_flat(out, limit, depth),
+            Object::Array(_) | Object::HashTable(_) => {
+                let Some((open, close, entries)) = self.entries(depth) else {
+                    out.push_str(if let Object::Array(_) = self {
+                        "[...]"
+                    } else {
+                        "{...}"
+                    });
+                    return out.len() <= limit;
+                };
+                out.push(open);
+                for (index, entry) in entries.into_iter().enumerate() {
+                    if index > 0 {
+                        out.push_str(", ");
+                    }
+                    let fits = match entry {
+                        Entry::Value(value) => value.inspect_flat(out, limit, depth + 1),
+                        Entry::Pair(key, value) => {
+                            key.inspect_flat(out, limit, depth + 1) && {
+                                out.push_str(": ");
+                                value.inspect_flat(out, limit, depth + 1)
+                            }
+                        }
+                        Entry::More(count) => {
+                            out.push_str(&format!("...{} more", count));
+                            true
+                        }
+                    };
+                    if !fits {
+                        return false;
+                    }
+                }
+                out.push(close);
+            }
+            value => {
+                let text = value.to_string();
+                out.push_str(&text);
+                if text.contains('\n') {
+                    return false;
+                }
+            }
+        }
+        out.len() <= limit
+    }
+
+    //`None` for values that aren't collections or that are nested too deep to be shown
+    fn entries(&self, depth: usize) -> Option<(char, char, Vec<Entry<'_>>)> {
+        let (open, close, len, mut entries) = match self {
+            Object::Array(array) => {
+                let entries = array.iter().take(INSPECT_ITEMS).map(Entry::Value);
+                ('[', ']', array.len(), entries.collect::<Vec<_>>())
+            }
+            Object::HashTable(hash) => {
+                //sorted so that the same table always prints the same way
+                let mut pairs: Vec<_> = hash.0.iter().collect();
+                pairs.sort_by_cached_key(|(key, _)| key.inspect(usize::MAX));
+                let entries = pairs.into_iter().take(INSPECT_ITEMS);
+                let entries = entries.map(|(key, value)| Entry::Pair(key, value));
+                ('{', '}', hash.0.len(), entries.collect())
+            }
+            _ => return None,
+        };
+        if depth >= INSPECT_DEPTH && len > 0 {
+            return None;
+        }
+        if len > INSPECT_ITEMS {
+            entries.push(Entry::More(len - INSPECT_ITEMS));
+        }
+        Some((open, close, entries))
+    }
+}
+
 impl Function {
-    pub fn new(parameters: Vec<Identifier>, body: BlockStatement, env: SharedEnv) -> Self {
+    pub fn new(parameters: Vec<Identifier>, body: Rc<BlockStatement>, env: SharedEnv) -> Self {
         Self {
             parameters,
+            source: body.clone(),
             body,
             env,
         }
@@ -299,7 +712,8 @@ impl Object {
             Object::Bool(_) => "bool",
             Object::String(_) => "string",
             Object::BuiltInFn(builtin) => builtin.name(),
-            Object::Function(_) => "function",
+            Object::Function(_) | Object::Closure(_) | Object::NativeFn(_) => "function",
+            Object::Native(native) => native.type_name(),
             Object::Return(_) => "return",
             Object::Array(_) => "array",
             Object::HashTable(_) => "object",
@@ -346,7 +760,9 @@ impl Object {
             Object::Int(int) => int != 0,
             Object::Bool(bool) => bool,
             Object::String(str) => !str.is_empty(),
-            Object::Function(_) => true,
+            Object::Function(_) | Object::Closure(_) | Object::NativeFn(_) | Object::Native(_) => {
+                true
+            }
             other => {
                 return Err(EvalError::CoercionError {
                     target: TRUE.as_typeof(),
@@ -362,7 +778,9 @@ impl Object {
 
     pub fn minus(self) -> Result<Object> {
         match self {
-            Object::Int(int) => Ok(Object::Int(-int)),
+            Object::Int(int) => Ok(Object::Int(
+                int.checked_neg().ok_or(EvalError::Overflow("Minus"))?,
+            )),
             operand => Err(EvalError::UnaryOpError {
                 operator: UnaryOperator::Minus,
                 operand: operand.to_string(),
@@ -372,7 +790,9 @@ impl Object {
 
     pub fn add(self, rhs: Object) -> Result<Object> {
         Ok(match (self, rhs) {
-            (Object::Int(lhs), Object::Int(rhs)) => Object::Int(lhs + rhs),
+            (Object::Int(lhs), Object::Int(rhs)) => {
+                Object::Int(lhs.checked_add(rhs).ok_or(EvalError::Overflow("Add"))?)
+            }
             (Object::String(lhs), Object::String(rhs)) => {
                 Object::String(format!("{}{}", lhs, rhs).into())
             }
@@ -388,7 +808,9 @@ impl Object {
 
     pub fn sub(self, rhs: Object) -> Result<Object> {
         Ok(match (self, rhs) {
-            (Object::Int(lhs), Object::Int(rhs)) => Object::Int(lhs - rhs),
+            (Object::Int(lhs), Object::Int(rhs)) => {
+                Object::Int(lhs.checked_sub(rhs).ok_or(EvalError::Overflow("Sub"))?)
+            }
             (lhs, rhs) => {
                 return Err(EvalError::BinaryOpError {
                     operator: BinaryOperator::Sub,
@@ -401,7 +823,9 @@ impl Object {
 
     pub fn mul(self, rhs: Object) -> Result<Object> {
         Ok(match (self, rhs) {
-            (Object::Int(lhs), Object::Int(rhs)) => Object::Int(lhs * rhs),
+            (Object::Int(lhs), Object::Int(rhs)) => {
+                Object::Int(lhs.checked_mul(rhs).ok_or(EvalError::Overflow("Mul"))?)
+            }
             (lhs, rhs) => {
                 return Err(EvalError::BinaryOpError {
                     operator: BinaryOperator::Mul,
@@ -414,7 +838,10 @@ impl Object {
 
     pub fn div(self, rhs: Object) -> Result<Object> {
         Ok(match (self, rhs) {
-            (Object::Int(lhs), Object::Int(rhs)) => Object::Int(lhs / rhs),
+            (Object::Int(_), Object::Int(0)) => return Err(EvalError::DivisionByZero),
+            (Object::Int(lhs), Object::Int(rhs)) => {
+                Object::Int(lhs.checked_div(rhs).ok_or(EvalError::Overflow("Div"))?)
+            }
             (lhs, rhs) => {
                 return Err(EvalError::BinaryOpError {
                     operator: BinaryOperator::Div,
@@ -425,6 +852,29 @@ impl Object {
         })
     }
 
+    pub fn index(self, index: Object) -> Result<Object> {
+        Ok(match (self, index) {
+            (Object::Array(array), Object::Int(index)) => {
+                let value = if index.is_negative() {
+                    array.len().checked_sub(index.unsigned_abs() as usize)
+                } else {
+                    Some(index as usize)
+                };
+                value.map_or(NIL, |x| array.get(x).cloned().unwrap_or(NIL))
+            }
+            (Object::HashTable(table), anything) => table.get(anything).cloned().unwrap_or(NIL),
+            (Object::Native(native), Object::String(name)) => {
+                native.get_property(&name).unwrap_or(NIL)
+            }
+            (container, index) => {
+                return Err(EvalError::IndexError {
+                    container: container.to_string(),
+                    index: index.to_string(),
+                })
+            }
+        })
+    }
+
     pub fn eq(self, rhs: Object) -> bool {
         match (self, rhs) {
             (Object::Int(lhs), Object::Int(rhs)) => lhs == rhs,
@@ -477,3 +927,57 @@ impl From<bool> for Object {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use insta::assert_snapshot;
+
+    fn array(items: impl IntoIterator<Item = Object>) -> Object {
+        Object::Array(Array::new(items.into_iter().collect()))
+    }
+
+    #[test]
+    fn look_up_string_keys_without_interning() {
+        let key = || Object::String("object_test_dynamic_key".into());
+        let hash: HashTable = [(key(), Object::Int(1))].into_iter().collect();
+
+        assert_eq!(hash.get(key()), Some(&Object::Int(1)));
+        assert_eq!(hash.get(Object::String("other".into())), None);
+        assert_eq!(
+            crate::interner::Symbol::lookup("object_test_dynamic_key"),
+            None
+        );
+    }
+
+    #[test]
+    fn inspect_values() {
+        assert_eq!(Object::String("1".into()).inspect(80), "\"1\"");
+        assert_eq!(Object::Int(1).inspect(80), "1");
+        let hash: HashTable = [
+            (Object::String("b".into()), Object::Nil),
+            (Object::String("a".into()), array([Object::Bool(true)])),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(
+            Object::HashTable(Box::new(hash)).inspect(80),
+            "{\"a\": [true], \"b\": nil}"
+        );
+
+        let nested = (0..8).fold(Object::Int(0), |x, _| array([x]));
+        assert_eq!(nested.inspect(80), "[[[[[[[...]]]]]]]");
+    }
+
+    #[test]
+    fn inspect_large_values() {
+        let words = array(["alpha", "beta", "gamma"].map(|x| Object::String(x.into())));
+        let numbers = array((0..40).map(Object::Int));
+        let hash: HashTable = [(Object::String("words".into()), words.clone())]
+            .into_iter()
+            .collect();
+        let value = array([words, numbers, Object::HashTable(Box::new(hash))]);
+
+        assert_snapshot!(value.inspect(40));
+    }
+}
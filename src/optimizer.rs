@@ -0,0 +1,249 @@
+use std::{collections::HashMap, rc::Rc};
+
+use crate::ast::{BlockStatement, Expression, IfExpression, Literal, Statement};
+use crate::object::Object;
+use crate::resolver::Binding;
+
+/// Folds constant expressions, prunes `if` branches that can never run and inlines local
+/// `let`s bound to a constant. Runs after the resolver, since inlining relies on the slot of each
+/// identifier. Anything that would fail at runtime is left untouched so it still fails there.
+pub fn optimize(statements: &mut [Statement]) {
+    let mut optimizer = Optimizer {
+        scopes: vec![HashMap::new()],
+    };
+    optimizer.optimize_statements(statements);
+}
+
+struct Optimizer {
+    //constant `let`s of each function scope, by slot
+    scopes: Vec<HashMap<usize, Literal>>,
+}
+
+impl Optimizer {
+    fn optimize_statements(&mut self, statements: &mut [Statement]) {
+        for statement in statements {
+            match statement {
+                Statement::Let { value, slot, .. } => {
+                    self.optimize_expression(value);
+                    //globals can be replaced from the host or by a later REPL input, so only
+                    //`let`s local to a function are constant
+                    let local = self.scopes.len() > 1;
+                    if let (Expression::Literal(literal), Some(slot)) = (value.as_ref(), slot) {
+                        if local && constant(literal).is_some() {
+                            self.current_scope().insert(*slot, literal.clone());
+                        }
+                    }
+                }
+                Statement::Return(exp) | Statement::Expression(exp) => {
+                    self.optimize_expression(exp)
+                }
+            }
+        }
+    }
+
+    //`if` blocks share the scope of their function, but their `let`s may never run
+    fn optimize_branch(&mut self, block: &mut BlockStatement) {
+        let constants = self.current_scope().clone();
+        self.optimize_statements(&mut block.0);
+        *self.current_scope() = constants;
+    }
+
+    fn optimize_expression(&mut self, expression: &mut Expression) {
+        match expression {
+            Expression::Identifier(exp) => {
+                if let Some(Binding::Local { depth, slot }) = exp.binding {
+                    let scope = &self.scopes[self.scopes.len() - 1 - depth];
+                    if let Some(literal) = scope.get(&slot) {
+                        *expression = literal.clone().into();
+                    }
+                }
+            }
+            Expression::Literal(Literal::Array(array)) => {
+                array.iter_mut().for_each(|x| self.optimize_expression(x))
+            }
+            Expression::Literal(Literal::Hash(hash)) => hash.iter_mut().for_each(|(key, value)| {
+                self.optimize_expression(key);
+                self.optimize_expression(value);
+            }),
+            Expression::Literal(_) => {}
+            Expression::UnaryExpression(exp) => {
+                self.optimize_expression(&mut exp.value);
+                let folded = literal_operand(&exp.value)
+                    .and_then(|operand| exp.operator.apply(operand).ok())
+                    .and_then(into_literal);
+                if let Some(literal) = folded {
+                    *expression = literal.into();
+                }
+            }
+            Expression::BinaryExp(exp) => {
+                self.optimize_expression(&mut exp.lhs);
+                self.optimize_expression(&mut exp.rhs);
+                let folded = literal_operand(&exp.lhs)
+                    .zip(literal_operand(&exp.rhs))
+                    .and_then(|(lhs, rhs)| exp.operator.apply(lhs, rhs).ok())
+                    .and_then(into_literal);
+                if let Some(literal) = folded {
+                    *expression = literal.into();
+                }
+            }
+            Expression::If(exp) => {
+                self.optimize_expression(&mut exp.condition);
+                match literal_operand(&exp.condition).and_then(|x| x.into_bool().ok()) {
+                    Some(condition) => {
+                        let branch = match condition {
+                            true => Some(&mut exp.consequence),
+                            false => exp.alternative.as_mut(),
+                        };
+                        let Some(branch) = branch else {
+                            *expression = Literal::Nil.into();
+                            return;
+                        };
+                        self.optimize_branch(branch);
+                        *expression = prune(std::mem::take(branch));
+                    }
+                    None => {
+                        self.optimize_branch(&mut exp.consequence);
+                        if let Some(alternative) = &mut exp.alternative {
+                            self.optimize_branch(alternative);
+                        }
+                    }
+                }
+            }
+            Expression::IndexExpression(exp) => {
+                self.optimize_expression(&mut exp.container);
+                self.optimize_expression(&mut exp.index);
+            }
+            Expression::Call(exp) => {
+                self.optimize_expression(&mut exp.function);
+                exp.arguments
+                    .iter_mut()
+                    .for_each(|x| self.optimize_expression(x));
+            }
+            //the body is optimized as a copy, the original is kept for display
+            Expression::Function(function) => {
+                let mut body = BlockStatement::clone(&function.body);
+                self.scopes.push(HashMap::new());
+                self.optimize_statements(&mut body.0);
+                self.scopes.pop();
+                if body != *function.body {
+                    let source = std::mem::replace(&mut function.body, Rc::new(body));
+                    function.source.get_or_insert(source);
+                }
+            }
+        }
+    }
+
+    fn current_scope(&mut self) -> &mut HashMap<usize, Literal> {
+        self.scopes.last_mut().expect("global scope")
+    }
+}
+
+//the branch that always runs, as a plain expression when it is a single one
+fn prune(branch: BlockStatement) -> Expression {
+    match <[Statement; 1]>::try_from(branch.0) {
+        Ok([Statement::Expression(exp)]) => *exp,
+        Ok(statements) => always_true(statements.into()),
+        Err(statements) if statements.is_empty() => Literal::Nil.into(),
+        Err(statements) => always_true(statements),
+    }
+}
+
+fn always_true(statements: Vec<Statement>) -> Expression {
+    Expression::If(IfExpression {
+        condition: Literal::True.into_exp(),
+        consequence: BlockStatement::new(statements),
+        alternative: None,
+    })
+}
+
+fn literal_operand(expression: &Expression) -> Option<Object> {
+    match expression {
+        Expression::Literal(literal) => constant(literal),
+        _ => None,
+    }
+}
+
+//arrays and hashes are compared by identity, so only scalars count as constants
+fn constant(literal: &Literal) -> Option<Object> {
+    match literal {
+        Literal::Int(int) => Some(Object::Int(*int)),
+        Literal::String(string) => Some(Object::String(string.clone())),
+        Literal::True => Some(Object::Bool(true)),
+        Literal::False => Some(Object::Bool(false)),
+        Literal::Nil => Some(Object::Nil),
+        Literal::Array(_) | Literal::Hash(_) => None,
+    }
+}
+
+fn into_literal(object: Object) -> Option<Literal> {
+    match object {
+        Object::Int(int) => Some(Literal::Int(int)),
+        Object::String(string) => Some(Literal::String(string)),
+        Object::Bool(true) => Some(Literal::True),
+        Object::Bool(false) => Some(Literal::False),
+        Object::Nil => Some(Literal::Nil),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser, resolver::Resolver};
+    use insta::assert_yaml_snapshot;
+
+    fn optimize_program(input: &str) -> Vec<Statement> {
+        let mut nodes = Parser::new(Lexer::new(input)).nodes;
+        Resolver::new().resolve(&mut nodes).unwrap();
+        optimize(&mut nodes);
+        nodes
+    }
+
+    #[test]
+    fn fold_constant_expressions() {
+        let input = [
+            "1 + 2 * 3;",
+            r#""foo" + "bar";"#,
+            "!(1 < 2) == false;",
+            "-(10 / 2);",
+            "[1 + 1, {2 * 2: 3 - 3}];",
+            "5 + true;",
+            "1 / 0;",
+            "9223372036854775807 + 1;",
+        ];
+
+        let result: Vec<_> = input.iter().map(|x| optimize_program(x)).collect();
+        assert_yaml_snapshot!(result);
+    }
+
+    #[test]
+    fn prune_dead_branches() {
+        let input = [
+            "if (true) { 1 } else { 2 };",
+            "if (1 > 2) { 1 } else { 2 };",
+            "if (false) { 1 };",
+            "if (\"\") { 1 } else { let a = 2; a };",
+            "if (true) { };",
+            "fn(x) { if (x) { 1 } else { 2 } };",
+        ];
+
+        let result: Vec<_> = input.iter().map(|x| optimize_program(x)).collect();
+        assert_yaml_snapshot!(result);
+    }
+
+    #[test]
+    fn inline_constant_lets() {
+        let input = [
+            "let a = 2; let b = a * 3; b + 1;",
+            "fn() { let a = 2; let b = a * 3; b + 1 };",
+            "let a = 2; fn(x) { let b = a; x + b };",
+            "let f = fn() { a }; let a = 1; f();",
+            "let a = [1]; a;",
+            "fn(x) { if (x) { let b = 1; }; b };",
+            "let x = 1; fn(x) { x };",
+        ];
+
+        let result: Vec<_> = input.iter().map(|x| optimize_program(x)).collect();
+        assert_yaml_snapshot!(result);
+    }
+}
@@ -1,29 +1,130 @@
-use self::ast::Statement;
+use self::ast::{Expression, Statement};
 use self::token_parser::TokenParser;
 use crate::ast;
-use crate::lexer::Lexer;
+use crate::lexer::{Lexer, Span};
+use crate::token::Token;
 use crate::token_parser;
+use crate::visitor::{walk_expression, walk_statement, Visitor};
 use anyhow::Error;
+use fnv::FnvHashMap;
+use std::fmt::{self, Display};
+use std::vec;
+
+/// The source ended in the middle of a statement, so more input could still make it valid.
+/// Holds the token that was expected, if the parser knew which one.
+#[derive(Debug, PartialEq, Eq)]
+pub struct UnexpectedEof(pub Option<Token>);
+
+impl Display for UnexpectedEof {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            Some(token) => write!(f, "Expected token {:?} but found the end of file", token),
+            None => write!(f, "Unexpected end of file, no more tokens"),
+        }
+    }
+}
+
+impl std::error::Error for UnexpectedEof {}
 
 #[derive(Debug)]
 pub struct Parser {
     pub nodes: Vec<Statement>,
+    //source covered by each of the nodes
+    pub spans: Vec<Span>,
+    //same for the nodes nested in them
+    pub node_spans: NodeSpans,
     pub errors: Vec<Error>,
+    //tokens around the place each error was found
+    pub error_spans: Vec<Span>,
 }
 
 impl Parser {
     pub fn new(lexer: Lexer) -> Self {
         let mut nodes = Vec::new();
+        let mut spans = Vec::new();
         let mut errors = Vec::new();
-        let mut tokens = TokenParser::new(lexer.tokens);
+        let mut error_spans = Vec::new();
+        let mut tokens = TokenParser::new(lexer.tokens, &lexer.spans);
 
         while let Some(current) = tokens.next() {
-            tokens
-                .parse_statement(current)
-                .map_or_else(|err| errors.push(err), |val| nodes.push(val))
+            let built = (tokens.statement_spans.len(), tokens.expression_spans.len());
+            let result = tokens.parse_statement(current);
+            let last = tokens.position();
+            match result {
+                Ok(node) => {
+                    nodes.push(node);
+                    spans.extend(tokens.statement_spans.last());
+                }
+                //the culprit is either the last token read or the one that was expected to follow
+                Err(err) => {
+                    errors.push(err);
+                    let next = lexer.spans.get(last + 1);
+                    error_spans.push(next.map_or(lexer.spans[last], |x| lexer.spans[last].to(*x)));
+                    //the nodes of the statement were thrown away
+                    tokens.statement_spans.truncate(built.0);
+                    tokens.expression_spans.truncate(built.1);
+                }
+            }
+        }
+
+        let mut pairs = NodeSpans::default();
+        SpanPairs {
+            statements: tokens.statement_spans.into_iter(),
+            expressions: tokens.expression_spans.into_iter(),
+            spans: &mut pairs,
+        }
+        .visit_program(&nodes);
+        Parser {
+            nodes,
+            spans,
+            node_spans: pairs,
+            errors,
+            error_spans,
+        }
+    }
+}
+
+/// Source covered by each statement and expression of a program, nested ones included.
+///
+/// Nodes are looked up by address, so the spans hold for the tree the parser built as long as
+/// its nodes aren't moved or replaced, by the optimizer for instance.
+#[derive(Debug, Default)]
+pub struct NodeSpans {
+    statements: FnvHashMap<*const Statement, Span>,
+    expressions: FnvHashMap<*const Expression, Span>,
+}
+
+impl NodeSpans {
+    pub fn statement(&self, statement: &Statement) -> Option<Span> {
+        self.statements.get(&(statement as *const _)).copied()
+    }
+
+    pub fn expression(&self, expression: &Expression) -> Option<Span> {
+        self.expressions.get(&(expression as *const _)).copied()
+    }
+}
+
+//the token parser builds the children of a node before the node itself, so walking the
+//tree children first meets the nodes in the order their spans were recorded
+struct SpanPairs<'a> {
+    statements: vec::IntoIter<Span>,
+    expressions: vec::IntoIter<Span>,
+    spans: &'a mut NodeSpans,
+}
+
+impl Visitor for SpanPairs<'_> {
+    fn visit_statement(&mut self, statement: &Statement) {
+        walk_statement(self, statement);
+        if let Some(span) = self.statements.next() {
+            self.spans.statements.insert(statement, span);
         }
+    }
 
-        Parser { nodes, errors }
+    fn visit_expression(&mut self, expression: &Expression) {
+        walk_expression(self, expression);
+        if let Some(span) = self.expressions.next() {
+            self.spans.expressions.insert(expression, span);
+        }
     }
 }
 
@@ -144,6 +245,17 @@ mod tests {
         assert_yaml_snapshot!(program.nodes);
     }
 
+    #[test]
+    fn parse_trailing_commas() {
+        let input = r#"[1, 2,]; {"a": 1, "b": 2,}; fn(x, y,) { f(x, y,) }"#;
+        let expected = r#"[1, 2]; {"a": 1, "b": 2}; fn(x, y) { f(x, y) }"#;
+        let program = Parser::new(Lexer::new(input));
+
+        assert!(program.errors.is_empty(), "errors: {:#?}", program.errors);
+        assert_eq!(program.nodes, Parser::new(Lexer::new(expected)).nodes);
+        assert!(!Parser::new(Lexer::new("[1,,]")).errors.is_empty());
+    }
+
     #[test]
     fn parse_expression_statement_identifier() {
         let input = "foobar;";
@@ -224,4 +336,88 @@ mod tests {
         assert!(program.errors.is_empty(), "errors: {:#?}", program.errors);
         assert_yaml_snapshot!(program.nodes);
     }
+
+    #[test]
+    fn report_unexpected_end_of_file() {
+        for input in [
+            "let a = ",
+            "fn(x) { x",
+            "if (a) { 1 } else",
+            "[1, 2",
+            "let a = 1",
+        ] {
+            let program = Parser::new(Lexer::new(input));
+            assert!(
+                program.errors[0].is::<UnexpectedEof>(),
+                "{}: {:?}",
+                input,
+                program.errors
+            );
+        }
+        let program = Parser::new(Lexer::new("let a = ;"));
+        assert!(!program.errors[0].is::<UnexpectedEof>());
+    }
+
+    #[test]
+    fn track_statement_spans() {
+        let input = "let a = fn(x) {\n  x\n};\na(1)\n-a";
+
+        let program = Parser::new(Lexer::new(input));
+        let spans: Vec<_> = program
+            .spans
+            .iter()
+            .map(|x| (&input[x.start..x.end], x.line))
+            .collect();
+        assert_eq!(spans, [("let a = fn(x) {\n  x\n};", 1), ("a(1)\n-a", 4)]);
+
+        let input = "let a = 1;\nlet = 2;\nputs(a";
+        let program = Parser::new(Lexer::new(input));
+        let spans: Vec<_> = program
+            .error_spans
+            .iter()
+            .map(|x| &input[x.start..x.end])
+            .collect();
+        assert_eq!(spans, ["= 2", "a"]);
+    }
+
+    //source of every node, parents first
+    struct Sources<'a> {
+        input: &'a str,
+        spans: &'a NodeSpans,
+        sources: Vec<&'a str>,
+    }
+
+    impl Visitor for Sources<'_> {
+        fn visit_statement(&mut self, statement: &Statement) {
+            let span = self.spans.statement(statement).expect("a statement span");
+            self.sources.push(&self.input[span.start..span.end]);
+            walk_statement(self, statement);
+        }
+
+        fn visit_expression(&mut self, expression: &Expression) {
+            let span = self
+                .spans
+                .expression(expression)
+                .expect("an expression span");
+            self.sources.push(&self.input[span.start..span.end]);
+            walk_expression(self, expression);
+        }
+    }
+
+    #[test]
+    fn track_nested_spans() {
+        let input = r#"let f = fn(x) { if (!x) { return [x, -1]; } else { {"a": x[0]} } };
+let x = [1, f(2) +;
+(f(1) + 2) * 3
+"#;
+        let program = Parser::new(Lexer::new(input));
+        assert!(!program.errors.is_empty());
+        let mut sources = Sources {
+            input,
+            spans: &program.node_spans,
+            sources: Vec::new(),
+        };
+        sources.visit_program(&program.nodes);
+        assert_yaml_snapshot!(sources.sources);
+    }
 }
@@ -0,0 +1,522 @@
+use std::collections::VecDeque;
+use std::fmt::{self, Display};
+
+use crate::ast::{BinaryOperator, BlockStatement, Expression, Literal, Statement, UnaryOperator};
+use crate::lexer::Comment;
+use crate::token::Identifier;
+
+const INDENT: &str = "    ";
+
+//binding power of each kind of expression, mirroring `Token::precedence` in the parser
+const UNARY: u8 = 6;
+const CALL: u8 = 7;
+const PRIMARY: u8 = 9;
+
+/// Renders the AST back to Monkey source, one statement per line, so that parsing the
+/// output gives back the same tree.
+///
+/// Lists longer than `width` are split one item per line, and the comments and blank lines
+/// given to `with_trivia` are written back next to the tokens they were attached to.
+#[derive(Debug)]
+pub struct Printer {
+    out: String,
+    indent: usize,
+    width: usize,
+    //tokens printed so far, leaving out parentheses, commas and semicolons since those
+    //are the only ones that may differ from the source
+    index: usize,
+    line_start: bool,
+    comments: VecDeque<Comment>,
+    blank_lines: VecDeque<usize>,
+}
+
+impl Default for Printer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Printer {
+    pub fn new() -> Self {
+        Self {
+            out: String::new(),
+            indent: 0,
+            width: usize::MAX,
+            index: 0,
+            line_start: true,
+            comments: VecDeque::new(),
+            blank_lines: VecDeque::new(),
+        }
+    }
+
+    /// `comments` and `blank_lines` refer to tokens by the same counting as `index`.
+    pub fn with_trivia(width: usize, comments: Vec<Comment>, blank_lines: Vec<usize>) -> Self {
+        Self {
+            width,
+            comments: comments.into(),
+            blank_lines: blank_lines.into(),
+            ..Self::new()
+        }
+    }
+
+    pub fn finish(mut self) -> String {
+        //comments after the last token
+        if !self.comments.is_empty() {
+            if !self.line_start {
+                self.newline();
+            }
+            self.flush_comments();
+        }
+        self.out
+    }
+
+    pub fn print_program(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            self.print_statement(statement, false);
+            self.newline();
+        }
+    }
+
+    /// The last statement of a block is its value, so it is printed without a semicolon.
+    pub fn print_statement(&mut self, statement: &Statement, last: bool) {
+        match statement {
+            Statement::Let {
+                identifier, value, ..
+            } => {
+                self.token("let");
+                self.write(" ");
+                self.token(identifier.as_str());
+                self.write(" ");
+                self.token("=");
+                self.write(" ");
+                self.print_expression(value);
+                self.write(";");
+            }
+            Statement::Return(value) => {
+                self.token("return");
+                self.write(" ");
+                self.print_expression(value);
+                self.write(";");
+            }
+            Statement::Expression(value) => {
+                self.print_expression(value);
+                //without it a following `(` or `[` would turn into a call or an index
+                if !last {
+                    self.write(";");
+                }
+            }
+        }
+    }
+
+    pub fn print_block(&mut self, block: &BlockStatement) {
+        self.token("{");
+        if block.0.is_empty() && !self.has_comments(self.index, self.index) {
+            return self.token("}");
+        }
+        self.indent += 1;
+        for (index, statement) in block.0.iter().enumerate() {
+            self.newline();
+            self.print_statement(statement, index == block.0.len() - 1);
+        }
+        //a block with nothing but comments
+        while self.comments.front().is_some_and(|x| x.token <= self.index) {
+            let comment = self.comments.pop_front().unwrap();
+            if comment.trailing {
+                self.out.push(' ');
+            } else {
+                self.newline();
+            }
+            self.write_comment(&comment);
+        }
+        self.indent -= 1;
+        self.newline();
+        self.token("}");
+    }
+
+    pub fn print_function(&mut self, parameters: &[Identifier], body: &BlockStatement) {
+        self.token("fn");
+        self.write("(");
+        self.print_list(parameters, 3, &|printer, parameter| {
+            printer.token(parameter.as_str())
+        });
+        self.write(") ");
+        self.print_block(body);
+    }
+
+    pub fn print_expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::Literal(literal) => self.print_literal(literal),
+            Expression::Identifier(exp) => self.token(exp.ident.as_str()),
+            Expression::UnaryExpression(exp) => {
+                self.token(&exp.operator.to_string());
+                self.print_operand(&exp.value, UNARY);
+            }
+            Expression::BinaryExp(exp) => {
+                let precedence = exp.operator.precedence();
+                self.print_operand(&exp.lhs, precedence);
+                self.write(" ");
+                self.token(&exp.operator.to_string());
+                self.write(" ");
+                //operators are left associative, so a right operand of the same precedence
+                //was grouped in the source
+                self.print_operand(&exp.rhs, precedence + 1);
+            }
+            Expression::If(exp) => {
+                self.token("if");
+                self.write(" (");
+                self.print_expression(&exp.condition);
+                self.write(") ");
+                self.print_block(&exp.consequence);
+                if let Some(alternative) = &exp.alternative {
+                    self.write(" ");
+                    self.token("else");
+                    self.write(" ");
+                    self.print_block(alternative);
+                }
+            }
+            Expression::IndexExpression(exp) => {
+                self.print_operand(&exp.container, CALL);
+                self.token("[");
+                self.print_expression(&exp.index);
+                self.token("]");
+            }
+            Expression::Function(function) => {
+                self.print_function(&function.parameters, &function.body)
+            }
+            Expression::Call(exp) => {
+                self.print_operand(&exp.function, CALL);
+                self.write("(");
+                self.print_list(&exp.arguments, 1, &Self::print_expression);
+                self.write(")");
+            }
+        }
+    }
+
+    fn print_literal(&mut self, literal: &Literal) {
+        match literal {
+            Literal::Int(int) => self.token(&int.to_string()),
+            Literal::String(string) => self.token(&format!("\"{}\"", string)),
+            Literal::True => self.token("true"),
+            Literal::False => self.token("false"),
+            Literal::Nil => self.token("nil"),
+            Literal::Array(array) => {
+                self.token("[");
+                self.print_list(array, 1, &Self::print_expression);
+                self.token("]");
+            }
+            Literal::Hash(hash) => {
+                self.token("{");
+                self.print_list(hash, 1, &|printer, (key, value)| {
+                    printer.print_expression(key);
+                    printer.token(":");
+                    printer.write(" ");
+                    printer.print_expression(value);
+                });
+                self.token("}");
+            }
+        }
+    }
+
+    //`tail` is the width of what closes the list, kept on the same line when it fits
+    fn print_list<T>(&mut self, items: &[T], tail: usize, print: &dyn Fn(&mut Self, &T)) {
+        if !self.should_break(items, tail, print) {
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    self.write(", ");
+                }
+                print(self, item);
+            }
+            return;
+        }
+        self.indent += 1;
+        for item in items {
+            self.newline();
+            print(self, item);
+            self.write(",");
+        }
+        self.indent -= 1;
+        self.newline();
+    }
+
+    //a list is split when it doesn't fit in the line or when it has comments inside
+    fn should_break<T>(&self, items: &[T], tail: usize, print: &dyn Fn(&mut Self, &T)) -> bool {
+        if items.is_empty() || self.width == usize::MAX {
+            return false;
+        }
+        let mut flat = Printer::new();
+        flat.print_list(items, tail, print);
+        if self.has_comments(self.index, self.index + flat.index) {
+            return true;
+        }
+        //only the last item may span several lines without splitting the list
+        let mut init = Printer::new();
+        init.print_list(&items[..items.len() - 1], tail, print);
+        if init.out.contains('\n') {
+            return true;
+        }
+        let column = self.out.len() - self.out.rfind('\n').map_or(0, |x| x + 1);
+        //functions inside the list span several lines, the first and the last have to fit
+        match (flat.out.split_once('\n'), flat.out.rsplit_once('\n')) {
+            (Some((first, _)), Some((_, last))) => {
+                column + first.len() > self.width
+                    || self.indent * INDENT.len() + last.len() + tail > self.width
+            }
+            _ => column + flat.out.len() + tail > self.width,
+        }
+    }
+
+    //wraps `expression` in parentheses when it binds looser than its parent
+    fn print_operand(&mut self, expression: &Expression, precedence: u8) {
+        if expression.precedence() < precedence {
+            self.write("(");
+            self.print_expression(expression);
+            self.write(")");
+        } else {
+            self.print_expression(expression);
+        }
+    }
+
+    fn has_comments(&self, from: usize, to: usize) -> bool {
+        self.comments
+            .iter()
+            .take_while(|x| x.token <= to)
+            .any(|x| x.token >= from)
+    }
+
+    fn token(&mut self, text: &str) {
+        //a comment that had a line of its own can't end up in the middle of one
+        let own_line = self
+            .comments
+            .iter()
+            .take_while(|x| x.token <= self.index)
+            .any(|x| !x.trailing);
+        if own_line && !self.line_start {
+            self.newline();
+        }
+        self.write(text);
+        self.index += 1;
+    }
+
+    fn write(&mut self, text: &str) {
+        if self.line_start {
+            self.line_start = false;
+            self.flush_comments();
+            while self.blank_lines.front().is_some_and(|x| *x < self.index) {
+                self.blank_lines.pop_front();
+            }
+            if self.blank_lines.front() == Some(&self.index) {
+                self.blank_lines.pop_front();
+                self.blank_line();
+            }
+        }
+        self.out.push_str(text);
+    }
+
+    //writes every comment attached to the next token, each on its own line
+    fn flush_comments(&mut self) {
+        while self.comments.front().is_some_and(|x| x.token <= self.index) {
+            let comment = self.comments.pop_front().unwrap();
+            if comment.blank_line {
+                self.blank_line();
+            }
+            self.write_comment(&comment);
+            self.newline();
+        }
+        self.line_start = false;
+    }
+
+    fn write_comment(&mut self, comment: &Comment) {
+        self.out.push_str("//");
+        self.out.push_str(&comment.text);
+    }
+
+    //turns the current, empty, line into a blank one, unless it follows an opening bracket
+    fn blank_line(&mut self) {
+        if matches!(
+            self.out.trim_end().chars().last(),
+            None | Some('{' | '[' | '(')
+        ) {
+            return;
+        }
+        self.out.truncate(self.out.trim_end_matches(' ').len());
+        self.out.push('\n');
+        self.push_indent();
+    }
+
+    fn newline(&mut self) {
+        while self
+            .comments
+            .front()
+            .is_some_and(|x| x.trailing && x.token <= self.index)
+        {
+            let comment = self.comments.pop_front().unwrap();
+            self.out.push(' ');
+            self.write_comment(&comment);
+        }
+        self.out.truncate(self.out.trim_end_matches(' ').len());
+        self.out.push('\n');
+        self.push_indent();
+        self.line_start = true;
+    }
+
+    fn push_indent(&mut self) {
+        for _ in 0..self.indent {
+            self.out.push_str(INDENT);
+        }
+    }
+}
+
+impl Expression {
+    fn precedence(&self) -> u8 {
+        match self {
+            Expression::BinaryExp(exp) => exp.operator.precedence(),
+            Expression::UnaryExpression(_) => UNARY,
+            Expression::Call(_) | Expression::IndexExpression(_) => CALL,
+            Expression::Literal(_)
+            | Expression::Identifier(_)
+            | Expression::If(_)
+            | Expression::Function(_) => PRIMARY,
+        }
+    }
+}
+
+impl BinaryOperator {
+    pub fn precedence(&self) -> u8 {
+        match self {
+            BinaryOperator::Eq | BinaryOperator::NotEq => 2,
+            BinaryOperator::Lt | BinaryOperator::Lte | BinaryOperator::Gt | BinaryOperator::Gte => {
+                3
+            }
+            BinaryOperator::Add | BinaryOperator::Sub => 4,
+            BinaryOperator::Mul | BinaryOperator::Div => 5,
+        }
+    }
+}
+
+impl Display for BinaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BinaryOperator::Eq => "==",
+            BinaryOperator::NotEq => "!=",
+            BinaryOperator::Lt => "<",
+            BinaryOperator::Lte => "<=",
+            BinaryOperator::Gt => ">",
+            BinaryOperator::Gte => ">=",
+            BinaryOperator::Add => "+",
+            BinaryOperator::Sub => "-",
+            BinaryOperator::Mul => "*",
+            BinaryOperator::Div => "/",
+        })
+    }
+}
+
+impl Display for UnaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            UnaryOperator::Not => "!",
+            UnaryOperator::Minus => "-",
+        })
+    }
+}
+
+impl Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut printer = Printer::new();
+        printer.print_statement(self, true);
+        f.write_str(&printer.finish())
+    }
+}
+
+impl Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut printer = Printer::new();
+        printer.print_expression(self);
+        f.write_str(&printer.finish())
+    }
+}
+
+impl Display for BlockStatement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut printer = Printer::new();
+        printer.print_block(self);
+        f.write_str(&printer.finish())
+    }
+}
+
+/// Source of a function value, as shown by `puts` and the REPL.
+pub fn function_to_string(parameters: &[Identifier], body: &BlockStatement) -> String {
+    let mut printer = Printer::new();
+    printer.print_function(parameters, body);
+    printer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compiler::Compiler, eval::Program, lexer::Lexer, parser::Parser, vm::Vm};
+    use insta::assert_snapshot;
+
+    fn print(input: &str) -> String {
+        let parser = Parser::new(Lexer::new(input));
+        assert!(parser.errors.is_empty(), "errors: {:#?}", parser.errors);
+        let mut printer = Printer::new();
+        printer.print_program(&parser.nodes);
+        printer.finish()
+    }
+
+    #[test]
+    fn print_program() {
+        let input = r#"
+            let add = fn(a, b) { return a + b; };
+            let fib = fn(x) { if (x <= 2) { 1 } else { fib(x - 1) + fib(x - 2) } };
+            let compose = fn(f, g) { fn(x) { let y = g(x); f(y) } };
+            if (!true) { puts("no") };
+            [1, "two", nil, {"a": [true, false], 3: fn() {}}][1];
+            fn(x) { x }(5)
+        "#;
+
+        assert_snapshot!(print(input));
+    }
+
+    #[test]
+    fn display_function_values() {
+        let input = "let add = fn(a, b) { let sum = a + b; sum }; add";
+        let expected = "fn(a, b) {\n    let sum = a + b;\n    sum\n}";
+
+        let object = Program::new().eval(Parser::new(Lexer::new(input))).unwrap();
+        assert_eq!(object.to_string(), expected);
+        let bytecode = Compiler::new()
+            .compile(Parser::new(Lexer::new(input)))
+            .unwrap();
+        assert_eq!(Vm::new().run(bytecode).unwrap().to_string(), expected);
+    }
+
+    #[test]
+    fn print_keeps_grouping() {
+        let input = [
+            "(1 + 2) * 3",
+            "1 + 2 * 3",
+            "1 - (2 - 3)",
+            "(1 - 2) - 3",
+            "-(1 + 2)",
+            "-a[0]",
+            "(-a)[0]",
+            "(a + b)(1)",
+            "!(a == b) != c",
+            "a < b == (c > d)",
+        ];
+
+        for input in input {
+            let printed = print(input);
+            let reparsed = Parser::new(Lexer::new(&printed)).nodes;
+            assert_eq!(
+                reparsed,
+                Parser::new(Lexer::new(input)).nodes,
+                "{}",
+                printed
+            );
+        }
+        let printed = input.iter().map(|x| print(x)).collect::<String>();
+        assert_snapshot!(printed);
+    }
+}
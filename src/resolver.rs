@@ -0,0 +1,476 @@
+use std::{mem, rc::Rc};
+
+use anyhow::{anyhow, Error, Result};
+use fnv::FnvHashMap;
+
+use crate::ast::{Expression, FunctionExpression, IdentifierExpression, Literal, Statement};
+use crate::object::{BuiltInFn, EvalError};
+use crate::token::Identifier;
+use crate::visitor::{walk_statement, Visitor};
+
+/// Where an identifier lives at runtime, computed ahead of evaluation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Binding {
+    /// `depth` environments up the chain, at index `slot`.
+    Local {
+        depth: usize,
+        slot: usize,
+    },
+    Builtin(BuiltInFn),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    index: usize,
+    defined: bool,
+}
+
+//one scope per function call, `if` blocks share the scope of their function like in `Environment`
+#[derive(Debug, Clone, Default)]
+struct Scope {
+    slots: FnvHashMap<Identifier, Slot>,
+    //shadowed names keep their slot, so there can be more slots than names
+    len: usize,
+}
+
+impl Scope {
+    fn declare(&mut self, name: Identifier) -> &mut Slot {
+        let len = &mut self.len;
+        self.slots.entry(name).or_insert_with(|| {
+            *len += 1;
+            Slot {
+                index: *len - 1,
+                defined: false,
+            }
+        })
+    }
+
+    //bindings already resolved to the previous slot of `name` keep using it
+    fn shadow(&mut self, name: Identifier) -> &mut Slot {
+        let slot = Slot {
+            index: self.len,
+            defined: false,
+        };
+        self.len += 1;
+        let entry = self.slots.entry(name).or_insert(slot);
+        *entry = slot;
+        entry
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+#[derive(Debug)]
+pub struct Resolver {
+    scopes: Vec<Scope>,
+    errors: Vec<Error>,
+    interactive: bool,
+    //globals the last resolved program shadowed, in case it fails before redefining them
+    shadowed: Vec<(Identifier, Slot)>,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self {
+            scopes: vec![Scope::default()],
+            errors: Vec::new(),
+            interactive: false,
+            shadowed: Vec::new(),
+        }
+    }
+
+    /// A resolver for interactive sessions, where a top-level `let` may define a name again.
+    /// The new binding shadows the previous one instead of replacing it, so functions defined
+    /// before it keep seeing the old value.
+    pub fn interactive() -> Self {
+        Self {
+            interactive: true,
+            ..Self::new()
+        }
+    }
+
+    /// Annotates every identifier with its binding, failing on the first undefined or
+    /// redefined one. Globals persist between calls, unless resolution fails.
+    pub fn resolve(&mut self, statements: &mut [Statement]) -> Result<()> {
+        let globals = self.scopes[0].clone();
+        self.shadowed.clear();
+        self.hoist(statements);
+        self.resolve_statements(statements);
+        match self.errors.drain(..).next() {
+            Some(err) => {
+                self.scopes[0] = globals;
+                Err(err)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Takes back the global `let`s of `statements` whose slot `is_set` says never got a
+    /// value, because evaluation failed before or while running them. A name they shadowed
+    /// goes back to its previous slot.
+    pub fn forget_unset(&mut self, statements: &[Statement], is_set: impl Fn(usize) -> bool) {
+        let mut lets = GlobalLets(Vec::new());
+        lets.visit_program(statements);
+        for (name, slot) in &mut self.scopes[0].slots {
+            if !slot.defined || !lets.0.contains(&slot.index) || is_set(slot.index) {
+                continue;
+            }
+            let previous = self.shadowed.iter().rev().find(|(shadowed, previous)| {
+                shadowed == name && previous.defined && is_set(previous.index)
+            });
+            match previous {
+                Some((_, previous)) => *slot = *previous,
+                None => slot.defined = false,
+            }
+        }
+    }
+
+    /// Slot of the global `name`, if a resolved program declared it.
+    pub fn global(&self, name: Identifier) -> Option<usize> {
+        self.scopes[0].slots.get(&name).map(|x| x.index)
+    }
+
+    /// Declares `name` in the global scope as if a `let` had defined it, returning its slot.
+    pub fn define_global(&mut self, name: Identifier) -> usize {
+        let slot = self.scopes[0].declare(name);
+        slot.defined = true;
+        slot.index
+    }
+
+    /// Globals declared so far with their slots, in declaration order.
+    pub fn global_slots(&self) -> Vec<(Identifier, usize)> {
+        let mut globals: Vec<_> = self.scopes[0]
+            .slots
+            .iter()
+            .filter(|(_, slot)| slot.defined)
+            .map(|(name, slot)| (*name, slot.index))
+            .collect();
+        globals.sort_unstable_by_key(|(_, index)| *index);
+        globals
+    }
+
+    /// Number of slots the global environment needs.
+    pub fn globals(&self) -> usize {
+        self.scopes[0].len()
+    }
+
+    //`let`s are declared up front so nested functions can refer to bindings defined later,
+    //just like looking them up by name at call time did
+    fn hoist(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            match statement {
+                Statement::Let {
+                    identifier, value, ..
+                } => {
+                    self.current_scope().declare(*identifier);
+                    self.hoist_expression(value);
+                }
+                Statement::Return(exp) | Statement::Expression(exp) => self.hoist_expression(exp),
+            }
+        }
+    }
+
+    fn hoist_expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::If(exp) => {
+                self.hoist_expression(&exp.condition);
+                self.hoist(&exp.consequence.0);
+                if let Some(alternative) = &exp.alternative {
+                    self.hoist(&alternative.0);
+                }
+            }
+            Expression::BinaryExp(exp) => {
+                self.hoist_expression(&exp.lhs);
+                self.hoist_expression(&exp.rhs);
+            }
+            Expression::UnaryExpression(exp) => self.hoist_expression(&exp.value),
+            Expression::IndexExpression(exp) => {
+                self.hoist_expression(&exp.container);
+                self.hoist_expression(&exp.index);
+            }
+            Expression::Call(exp) => {
+                self.hoist_expression(&exp.function);
+                exp.arguments.iter().for_each(|x| self.hoist_expression(x));
+            }
+            Expression::Literal(Literal::Array(array)) => {
+                array.iter().for_each(|x| self.hoist_expression(x));
+            }
+            Expression::Literal(Literal::Hash(hash)) => hash.iter().for_each(|(key, value)| {
+                self.hoist_expression(key);
+                self.hoist_expression(value);
+            }),
+            Expression::Literal(_) | Expression::Identifier(_) | Expression::Function(_) => {}
+        }
+    }
+
+    fn resolve_statements(&mut self, statements: &mut [Statement]) {
+        for statement in statements {
+            match statement {
+                Statement::Let {
+                    identifier,
+                    value,
+                    slot,
+                } => {
+                    let shadow = self.interactive
+                        && self.scopes.len() == 1
+                        && self.current_scope().declare(*identifier).defined;
+                    //a redefined function refers to itself like a hoisted one, any other value
+                    //still sees the previous binding, as in `let a = a + 1`
+                    let recursive = matches!(value.as_ref(), Expression::Function(_));
+                    if shadow {
+                        let previous = *self.current_scope().declare(*identifier);
+                        self.shadowed.push((*identifier, previous));
+                    }
+                    if shadow && recursive {
+                        self.current_scope().shadow(*identifier);
+                    }
+                    self.resolve_expression(value);
+                    let scope = self.current_scope();
+                    let declared = if shadow && !recursive {
+                        scope.shadow(*identifier)
+                    } else {
+                        scope.declare(*identifier)
+                    };
+                    let redefined = mem::replace(&mut declared.defined, true);
+                    *slot = Some(declared.index);
+                    if redefined {
+                        self.errors
+                            .push(EvalError::IdentifierAlreadyDefined(*identifier).into());
+                    }
+                }
+                Statement::Return(exp) | Statement::Expression(exp) => self.resolve_expression(exp),
+            }
+        }
+    }
+
+    fn resolve_expression(&mut self, expression: &mut Expression) {
+        match expression {
+            Expression::Identifier(exp) => self.resolve_identifier(exp),
+            Expression::Function(function) => self.resolve_function(function),
+            Expression::If(exp) => {
+                self.resolve_expression(&mut exp.condition);
+                self.resolve_statements(&mut exp.consequence.0);
+                if let Some(alternative) = &mut exp.alternative {
+                    self.resolve_statements(&mut alternative.0);
+                }
+            }
+            Expression::BinaryExp(exp) => {
+                self.resolve_expression(&mut exp.lhs);
+                self.resolve_expression(&mut exp.rhs);
+            }
+            Expression::UnaryExpression(exp) => self.resolve_expression(&mut exp.value),
+            Expression::IndexExpression(exp) => {
+                self.resolve_expression(&mut exp.container);
+                self.resolve_expression(&mut exp.index);
+            }
+            Expression::Call(exp) => {
+                self.resolve_expression(&mut exp.function);
+                exp.arguments
+                    .iter_mut()
+                    .for_each(|x| self.resolve_expression(x));
+            }
+            Expression::Literal(Literal::Array(array)) => {
+                array.iter_mut().for_each(|x| self.resolve_expression(x));
+            }
+            Expression::Literal(Literal::Hash(hash)) => hash.iter_mut().for_each(|(key, value)| {
+                self.resolve_expression(key);
+                self.resolve_expression(value);
+            }),
+            Expression::Literal(_) => {}
+        }
+    }
+
+    fn resolve_function(&mut self, function: &mut FunctionExpression) {
+        let mut scope = Scope::default();
+        for param in &function.parameters {
+            if mem::replace(&mut scope.declare(*param).defined, true) {
+                self.errors
+                    .push(EvalError::IdentifierAlreadyDefined(*param).into());
+            }
+        }
+        self.scopes.push(scope);
+        let body = Rc::make_mut(&mut function.body);
+        self.hoist(&body.0);
+        self.resolve_statements(&mut body.0);
+        self.scopes.pop();
+    }
+
+    fn resolve_identifier(&mut self, exp: &mut IdentifierExpression) {
+        let name = exp.ident;
+        let innermost = self.scopes.len() - 1;
+        let local = self
+            .scopes
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(index, scope)| {
+                scope
+                    .slots
+                    .get(&name)
+                    //a later `let` in the same scope isn't visible yet, one in an outer scope is
+                    .filter(|slot| slot.defined || index < innermost)
+                    .map(|slot| Binding::Local {
+                        depth: innermost - index,
+                        slot: slot.index,
+                    })
+            });
+        exp.binding = local.or_else(|| BuiltInFn::from_name(name.as_str()).map(Binding::Builtin));
+        if exp.binding.is_none() {
+            self.errors
+                .push(anyhow!("Identifier {} not found", exp.ident));
+        }
+    }
+
+    fn current_scope(&mut self) -> &mut Scope {
+        self.scopes.last_mut().expect("global scope")
+    }
+}
+
+//slots of the `let`s in the global scope, `if` blocks included but not function bodies
+struct GlobalLets(Vec<usize>);
+
+impl Visitor for GlobalLets {
+    fn visit_statement(&mut self, statement: &Statement) {
+        if let Statement::Let {
+            slot: Some(slot), ..
+        } = statement
+        {
+            self.0.push(*slot);
+        }
+        walk_statement(self, statement);
+    }
+
+    fn visit_function(&mut self, _: &FunctionExpression) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser};
+
+    fn resolve(input: &str) -> Result<Vec<Statement>> {
+        let mut nodes = Parser::new(Lexer::new(input)).nodes;
+        Resolver::new().resolve(&mut nodes)?;
+        Ok(nodes)
+    }
+
+    fn binding(expression: &Expression) -> Option<Binding> {
+        match expression {
+            Expression::Identifier(exp) => exp.binding,
+            _ => panic!("expected identifier, found {:?}", expression),
+        }
+    }
+
+    #[test]
+    fn resolve_depth_and_slot() {
+        let nodes = resolve("let a = 1; let f = fn(x) { let y = x; fn() { a + y } }; len").unwrap();
+
+        let Statement::Let {
+            value,
+            slot: Some(1),
+            ..
+        } = &nodes[1]
+        else {
+            panic!("expected let in slot 1")
+        };
+        let Expression::Function(outer) = value.as_ref() else {
+            panic!("expected function")
+        };
+        let Statement::Let { value, slot, .. } = &outer.body.0[0] else {
+            panic!("expected let")
+        };
+        assert_eq!(*slot, Some(1));
+        assert_eq!(binding(value), Some(Binding::Local { depth: 0, slot: 0 }));
+
+        let Statement::Expression(exp) = &outer.body.0[1] else {
+            panic!("expected expression")
+        };
+        let Expression::Function(inner) = exp.as_ref() else {
+            panic!("expected function")
+        };
+        let Statement::Expression(exp) = &inner.body.0[0] else {
+            panic!("expected expression")
+        };
+        let Expression::BinaryExp(sum) = exp.as_ref() else {
+            panic!("expected binary expression")
+        };
+        assert_eq!(
+            binding(&sum.lhs),
+            Some(Binding::Local { depth: 2, slot: 0 })
+        );
+        assert_eq!(
+            binding(&sum.rhs),
+            Some(Binding::Local { depth: 1, slot: 1 })
+        );
+
+        let Statement::Expression(exp) = &nodes[2] else {
+            panic!("expected expression")
+        };
+        assert_eq!(binding(exp), Some(Binding::Builtin(BuiltInFn::Len)));
+    }
+
+    #[test]
+    fn resolve_errors() {
+        let input = [
+            "foobar;",
+            "let foo = 3; let foo = 4;",
+            "let a = a;",
+            "fn(x, x) { x }",
+            "fn(x) { let x = 1; }",
+            "a; let a = 1;",
+            "fn() { let f = fn() { g() }; let g = fn() { 1 }; }; g",
+        ];
+
+        let result = input
+            .iter()
+            .map(|x| resolve(x).unwrap_err().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            result,
+            vec![
+                "Identifier foobar not found",
+                "Identifier foo already defined",
+                "Identifier a not found",
+                "Identifier x already defined",
+                "Identifier x already defined",
+                "Identifier a not found",
+                "Identifier g not found",
+            ]
+        );
+    }
+
+    #[test]
+    fn shadow_globals_in_interactive_mode() {
+        let mut resolver = Resolver::interactive();
+        let mut resolve = |input| resolver.resolve(&mut Parser::new(Lexer::new(input)).nodes);
+
+        assert!(resolve("let a = 1; let f = fn() { a };").is_ok());
+        assert!(resolve("let a = a + 1; let a = 3;").is_ok());
+        assert!(resolve("let f = fn() { f() };").is_ok());
+        assert!(resolve("let f = fn(x, x) { x };").is_err());
+        assert!(resolve("let g = fn() { let b = 1; let b = 2; };").is_err());
+        assert_eq!(resolver.global(Identifier::new("a")), Some(3));
+        assert_eq!(resolver.globals(), 5);
+        assert_eq!(resolver.global_slots().len(), 2);
+    }
+
+    #[test]
+    fn resolve_keeps_globals_only_on_success() {
+        let mut resolver = Resolver::new();
+        let mut resolve = |input| resolver.resolve(&mut Parser::new(Lexer::new(input)).nodes);
+
+        assert!(resolve("let a = 1; b").is_err());
+        assert!(resolve("let a = 1; a").is_ok());
+        assert!(resolve("let a = 2;").is_err());
+        assert!(resolve("a").is_ok());
+        assert_eq!(resolver.globals(), 1);
+    }
+}
@@ -0,0 +1,368 @@
+use crate::{
+    lexer::{Lexer, Span},
+    token::{Identifier, Token},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefinitionKind {
+    Let,
+    Parameter,
+}
+
+/// A name introduced by a `let` or a function parameter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Definition {
+    pub name: Identifier,
+    pub kind: DefinitionKind,
+    pub span: Span,
+    //scope the name is defined in, 0 being the global scope
+    pub scope: usize,
+    //`let f = fn...`
+    pub function: bool,
+    //index of the first token that can see the name in its own scope
+    visible_from: usize,
+}
+
+/// A use of a name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    pub name: Identifier,
+    pub span: Span,
+    pub scope: usize,
+    //index in `Symbols::definitions`, `None` for builtins and undefined names
+    pub definition: Option<usize>,
+}
+
+/// Every name a source defines or uses, and which definition each use refers to.
+///
+/// Names are bound with the same rules as the resolver, but the analysis only looks at the
+/// tokens so it still works on code that doesn't parse, like the one being typed in an editor.
+#[derive(Debug, Default)]
+pub struct Symbols {
+    pub definitions: Vec<Definition>,
+    pub references: Vec<Reference>,
+    //scope enclosing each scope, one per function literal after the global one
+    parents: Vec<Option<usize>>,
+}
+
+impl Symbols {
+    pub fn new(lexer: &Lexer) -> Self {
+        let tokens = &lexer.tokens;
+        let mut symbols = Symbols {
+            parents: vec![None],
+            ..Default::default()
+        };
+        //token index of each reference
+        let mut used_at = Vec::new();
+        //for each open brace, the scope to go back to if it starts a function body
+        let mut braces = Vec::new();
+        let mut scope = 0;
+        let mut index = 0;
+
+        while index < tokens.len() {
+            match (&tokens[index], tokens.get(index + 1)) {
+                (Token::Let, Some(Token::Identifier(name))) => {
+                    symbols.definitions.push(Definition {
+                        name: *name,
+                        kind: DefinitionKind::Let,
+                        span: lexer.spans[index + 1],
+                        scope,
+                        function: tokens.get(index + 2) == Some(&Token::Assign)
+                            && tokens.get(index + 3) == Some(&Token::Function),
+                        //`let a = a;` refers to an outer `a`
+                        visible_from: statement_end(tokens, index),
+                    });
+                    index += 2;
+                    continue;
+                }
+                (Token::Function, Some(Token::LParen)) => {
+                    symbols.parents.push(Some(scope));
+                    let parent = scope;
+                    scope = symbols.parents.len() - 1;
+                    index += 2;
+                    while let Some(token) = tokens.get(index) {
+                        match token {
+                            Token::Identifier(name) => symbols.definitions.push(Definition {
+                                name: *name,
+                                kind: DefinitionKind::Parameter,
+                                span: lexer.spans[index],
+                                scope,
+                                function: false,
+                                visible_from: index,
+                            }),
+                            Token::Comma => {}
+                            _ => break,
+                        }
+                        index += 1;
+                    }
+                    if tokens.get(index) == Some(&Token::RParen)
+                        && tokens.get(index + 1) == Some(&Token::LBrace)
+                    {
+                        braces.push(Some(parent));
+                        index += 2;
+                    } else {
+                        scope = parent;
+                    }
+                    continue;
+                }
+                (Token::LBrace, _) => braces.push(None),
+                (Token::RBrace, _) => {
+                    if let Some(Some(parent)) = braces.pop() {
+                        scope = parent;
+                    }
+                }
+                (Token::Identifier(name), _) => {
+                    used_at.push(index);
+                    symbols.references.push(Reference {
+                        name: *name,
+                        span: lexer.spans[index],
+                        scope,
+                        definition: None,
+                    });
+                }
+                _ => {}
+            }
+            index += 1;
+        }
+
+        for (index, used_at) in used_at.into_iter().enumerate() {
+            let reference = &symbols.references[index];
+            let definition = symbols.lookup(reference.name, Some(reference.scope), used_at, true);
+            symbols.references[index].definition = definition;
+        }
+        symbols
+    }
+
+    //a later definition isn't visible in its own scope yet, but it is from nested functions
+    //since they only run once the whole scope was evaluated
+    fn lookup(
+        &self,
+        name: Identifier,
+        mut current: Option<usize>,
+        index: usize,
+        mut innermost: bool,
+    ) -> Option<usize> {
+        while let Some(scope) = current {
+            let mut candidates = (0..self.definitions.len()).filter(|x| {
+                let definition = &self.definitions[*x];
+                definition.scope == scope && definition.name == name
+            });
+            let first = candidates.clone().next();
+            let visible = candidates.rfind(|x| self.definitions[*x].visible_from <= index);
+            let found = if innermost {
+                visible
+            } else {
+                visible.or(first)
+            };
+            if found.is_some() {
+                return found;
+            }
+            current = self.parents[scope];
+            innermost = false;
+        }
+        None
+    }
+
+    /// Definition of the name at byte `offset`, whether it is a use of the name or where it
+    /// gets defined.
+    pub fn definition_at(&self, offset: usize) -> Option<usize> {
+        let contains = |span: &Span| span.start <= offset && offset <= span.end;
+        self.definitions
+            .iter()
+            .position(|x| contains(&x.span))
+            .or_else(|| {
+                let reference = self.references.iter().find(|x| contains(&x.span))?;
+                reference.definition
+            })
+    }
+
+    /// Use of a name at byte `offset`.
+    pub fn reference_at(&self, offset: usize) -> Option<&Reference> {
+        self.references
+            .iter()
+            .find(|x| x.span.start <= offset && offset <= x.span.end)
+    }
+
+    /// Uses of the definition at `index` in `definitions`.
+    pub fn references_to(&self, index: usize) -> impl Iterator<Item = &Reference> {
+        self.references
+            .iter()
+            .filter(move |x| x.definition == Some(index))
+    }
+
+    /// Definition from an enclosing function that the one at `index` hides.
+    pub fn shadowed(&self, index: usize) -> Option<usize> {
+        let definition = &self.definitions[index];
+        let parent = self.parents[definition.scope];
+        self.lookup(definition.name, parent, definition.visible_from, false)
+    }
+
+    /// Scope that directly encloses `scope`, `None` for the global scope.
+    pub fn parent(&self, scope: usize) -> Option<usize> {
+        self.parents[scope]
+    }
+}
+
+//index of the semicolon ending the statement that starts at `start`, or of the token closing
+//the block it is in when the semicolon is missing
+pub(crate) fn statement_end(tokens: &[Token], start: usize) -> usize {
+    let mut depth = 0usize;
+    for (index, token) in tokens.iter().enumerate().skip(start) {
+        match token {
+            Token::LParen | Token::LBrace | Token::LBracket => depth += 1,
+            Token::RParen | Token::RBrace | Token::RBracket if depth == 0 => return index,
+            Token::RParen | Token::RBrace | Token::RBracket => depth -= 1,
+            Token::Semicolon if depth == 0 => return index,
+            _ => {}
+        }
+    }
+    tokens.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ast::IdentifierExpression,
+        eval::tests::CASES,
+        object::{BuiltInFn, EvalError},
+        parser::Parser,
+        resolver::{Binding, Resolver},
+        visitor::Visitor,
+    };
+
+    struct Bindings(Vec<Option<Binding>>);
+
+    impl Visitor for Bindings {
+        fn visit_identifier(&mut self, identifier: &IdentifierExpression) {
+            self.0.push(identifier.binding);
+        }
+    }
+
+    //what each use of a name refers to, as the text of the definition and its line
+    fn bindings(input: &str) -> Vec<(&str, Option<usize>)> {
+        let symbols = Symbols::new(&Lexer::new(input));
+        symbols
+            .references
+            .iter()
+            .map(|x| {
+                let definition = x.definition.map(|x| symbols.definitions[x].span.line);
+                (&input[x.span.start..x.span.end], definition)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bind_names_to_definitions() {
+        let input = "let a = 1;
+            let f = fn(a, b) {
+                let c = {a: b};
+                if (c) { g(a) } else { len(c) }
+            };
+            let g = fn(x) { let a = a; x + a };
+            missing";
+
+        assert_eq!(
+            bindings(input),
+            [
+                ("a", Some(2)),
+                ("b", Some(2)),
+                ("c", Some(3)),
+                ("g", Some(6)),
+                ("a", Some(2)),
+                ("len", None),
+                ("c", Some(3)),
+                ("a", Some(1)),
+                ("x", Some(6)),
+                ("a", Some(6)),
+                ("missing", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn find_definitions_and_uses() {
+        let input = "let add = fn(x, y) { x + y };\nadd(1, add(2, 3))";
+        let symbols = Symbols::new(&Lexer::new(input));
+
+        let add = symbols.definition_at(input.rfind("add").unwrap()).unwrap();
+        assert_eq!(symbols.definition_at(5), Some(add));
+        assert!(symbols.definitions[add].function);
+        assert_eq!(symbols.references_to(add).count(), 2);
+        let x = symbols.definition_at(input.find("x +").unwrap()).unwrap();
+        assert_eq!(symbols.definitions[x].kind, DefinitionKind::Parameter);
+        assert_eq!(symbols.parent(symbols.definitions[x].scope), Some(0));
+        assert_eq!(symbols.definition_at(input.find("1").unwrap()), None);
+        assert_eq!(symbols.shadowed(x), None);
+    }
+
+    //names are bound here without the resolver, so both have to agree on what each use of a
+    //name refers to
+    #[test]
+    fn agree_with_the_resolver() {
+        let inputs = CASES.iter().flat_map(|(_, inputs)| inputs.iter()).chain(&[
+            "let a = 1; let f = fn(a, b) { let c = {a: b}; if (c) { g(a) } else { len(c) } };
+            let g = fn(x) { let a = a; x + a }; f(1, 2)",
+            "let f = fn() { g() }; let g = fn() { let x = 1; fn(y) { [x, y, f] } }; f",
+            "let a = fn(a) { a }; let b = fn() { let b = b; b }; missing",
+            "let f = fn() { x }; let x = y; let y = 1;",
+        ]);
+        for input in inputs {
+            let lexer = Lexer::new(input);
+            let symbols = Symbols::new(&lexer);
+            let mut parser = Parser::new(lexer);
+            if !parser.errors.is_empty() {
+                continue;
+            }
+            let undefined = symbols.references.iter().find(|x| {
+                x.definition.is_none() && BuiltInFn::from_name(x.name.as_str()).is_none()
+            });
+            match Resolver::new().resolve(&mut parser.nodes) {
+                Ok(()) => assert_eq!(undefined, None, "{}", input),
+                Err(err) if err.downcast_ref::<EvalError>().is_some() => continue,
+                Err(err) => assert_eq!(
+                    undefined.map(|x| format!("Identifier {} not found", x.name)),
+                    Some(err.to_string()),
+                    "{}",
+                    input
+                ),
+            }
+
+            let mut bindings = Bindings(Vec::new());
+            bindings.visit_program(&parser.nodes);
+            assert_eq!(bindings.0.len(), symbols.references.len(), "{}", input);
+            //the scope and slot each use was resolved to
+            let targets: Vec<_> = symbols
+                .references
+                .iter()
+                .zip(&bindings.0)
+                .map(|(reference, binding)| match binding {
+                    Some(Binding::Local { depth, slot }) => {
+                        let mut scope = Some(reference.scope);
+                        for _ in 0..*depth {
+                            scope = scope.and_then(|x| symbols.parent(x));
+                        }
+                        Some((scope, *slot))
+                    }
+                    _ => None,
+                })
+                .collect();
+            for (reference, target) in symbols.references.iter().zip(&targets) {
+                let scope = reference.definition.map(|x| symbols.definitions[x].scope);
+                assert_eq!(scope, target.and_then(|x| x.0), "{}", input);
+            }
+            for (i, first) in symbols.references.iter().enumerate() {
+                for (j, second) in symbols.references.iter().enumerate() {
+                    assert_eq!(
+                        first.definition == second.definition,
+                        targets[i] == targets[j],
+                        "{}: {} and {}",
+                        input,
+                        first.name,
+                        second.name
+                    );
+                }
+            }
+        }
+    }
+}
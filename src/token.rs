@@ -1,9 +1,11 @@
 use std::fmt::Display;
 
 use anyhow::{anyhow, Result};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use smol_str::SmolStr;
 
+use crate::interner::Symbol;
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub enum Token {
     Illegal,
@@ -46,25 +48,49 @@ pub enum Token {
     Return,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
-pub struct Identifier(SmolStr);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Identifier(Symbol);
 
 impl Identifier {
     #[inline]
-    pub fn new(name: SmolStr) -> Self {
-        Self(name)
+    pub fn new(name: &str) -> Self {
+        Self(Symbol::intern(name))
+    }
+
+    /// The identifier `name`, if source code or the host already used it.
+    #[inline]
+    pub fn lookup(name: &str) -> Option<Self> {
+        Symbol::lookup(name).map(Self)
+    }
+
+    #[inline]
+    pub fn symbol(&self) -> Symbol {
+        self.0
     }
 
     #[inline]
-    pub fn inner(&self) -> SmolStr {
-        self.0.clone()
+    pub fn as_str(&self) -> &'static str {
+        self.0.as_str()
     }
 }
 
 impl Display for Identifier {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        self.0.fmt(f)?;
-        Ok(())
+        self.0.fmt(f)
+    }
+}
+
+//symbols are only meaningful inside this process, so identifiers are stored by name
+impl Serialize for Identifier {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Identifier {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let name = SmolStr::deserialize(deserializer)?;
+        Ok(Self::new(&name))
     }
 }
 
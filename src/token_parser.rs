@@ -2,39 +2,78 @@ use anyhow::{anyhow, ensure, Result};
 use std::{
     iter::{self, Peekable},
     ops::{Deref, DerefMut},
+    rc::Rc,
     vec::IntoIter,
 };
 
 use crate::{
     ast::{
         BinaryExpression, BlockStatement, CallExpression, Expression, FunctionExpression,
-        IfExpression, IndexExpression, Literal, Statement, UnaryExpression, UnaryOperator,
+        IdentifierExpression, IfExpression, IndexExpression, Literal, Statement, UnaryExpression,
+        UnaryOperator,
     },
+    lexer::Span,
+    parser::UnexpectedEof,
     token::{Identifier, Token},
 };
 
-pub struct TokenParser(Peekable<IntoIter<Token>>);
+pub struct TokenParser<'a> {
+    tokens: Peekable<IntoIter<Token>>,
+    //of each token
+    spans: &'a [Span],
+    //source covered by each statement and expression, in the order they were built, which
+    //puts the children of a node before it
+    pub statement_spans: Vec<Span>,
+    pub expression_spans: Vec<Span>,
+}
 
-impl Deref for TokenParser {
+impl Deref for TokenParser<'_> {
     type Target = Peekable<IntoIter<Token>>;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.tokens
     }
 }
 
-impl DerefMut for TokenParser {
+impl DerefMut for TokenParser<'_> {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+        &mut self.tokens
     }
 }
 
-impl TokenParser {
-    pub fn new(tokens: Vec<Token>) -> Self {
-        Self(tokens.into_iter().peekable())
+impl<'a> TokenParser<'a> {
+    pub fn new(tokens: Vec<Token>, spans: &'a [Span]) -> Self {
+        Self {
+            tokens: tokens.into_iter().peekable(),
+            spans,
+            statement_spans: Vec::new(),
+            expression_spans: Vec::new(),
+        }
+    }
+
+    /// Index of the last token read.
+    pub fn position(&self) -> usize {
+        self.spans.len() - self.tokens.len() - 1
+    }
+
+    //from token `first` to the last one read
+    fn span_from(&self, first: usize) -> Span {
+        self.spans[first].to(self.spans[self.position()])
+    }
+
+    fn finish(&mut self, first: usize, expression: Expression) -> Expression {
+        self.expression_spans.push(self.span_from(first));
+        expression
     }
 
     pub fn parse_statement(&mut self, token: Token) -> Result<Statement> {
+        let first = self.position();
+        let statement = self.parse_statement_kind(token)?;
+        self.statement_spans.push(self.span_from(first));
+        Ok(statement)
+    }
+
+    fn parse_statement_kind(&mut self, token: Token) -> Result<Statement> {
         match token {
             Token::Let => {
                 let identifier = self.try_ident()?;
@@ -44,6 +83,7 @@ impl TokenParser {
                 Ok(Statement::Let {
                     identifier,
                     value: Box::new(expression),
+                    slot: None,
                 })
             }
             Token::Return => {
@@ -61,6 +101,7 @@ impl TokenParser {
     }
 
     fn parse_expression(&mut self, current_token: Token, precedence: u8) -> Result<Expression> {
+        let first = self.position();
         let mut left = self.parse_prefix(current_token)?;
 
         while let Some(token) =
@@ -70,23 +111,26 @@ impl TokenParser {
                 let right = self
                     .try_next()
                     .and_then(|exp| self.parse_expression(exp, token.precedence()))?;
-                left = Expression::BinaryExp(BinaryExpression {
+                let binary = Expression::BinaryExp(BinaryExpression {
                     operator,
                     lhs: Box::new(left),
                     rhs: Box::new(right),
                 });
+                left = self.finish(first, binary);
             } else if matches!(token, Token::LParen) {
                 //WTF: how can I assign left at the same time it is being moved?
-                left = Expression::Call(CallExpression {
+                let call = Expression::Call(CallExpression {
                     function: left.boxed(),
                     arguments: self.parse_comma_list(&Token::RParen)?,
                 });
+                left = self.finish(first, call);
             } else if matches!(token, Token::LBracket) {
-                left = Expression::IndexExpression(IndexExpression {
+                let index = Expression::IndexExpression(IndexExpression {
                     container: left.boxed(),
                     index: self.try_parse()?.boxed(),
                 });
                 self.try_eat(&Token::RBracket)?;
+                left = self.finish(first, index);
             } else {
                 break;
             }
@@ -95,10 +139,21 @@ impl TokenParser {
         Ok(left)
     }
 
+    //a grouped expression is the one between the parentheses, which already has its span
     #[inline]
     fn parse_prefix(&mut self, token: Token) -> Result<Expression> {
+        let first = self.position();
+        if token == Token::LParen {
+            return self.parse_grouped_expression();
+        }
+        let expression = self.parse_prefix_kind(token)?;
+        Ok(self.finish(first, expression))
+    }
+
+    #[inline]
+    fn parse_prefix_kind(&mut self, token: Token) -> Result<Expression> {
         match token {
-            Token::Identifier(name) => Ok(Expression::Identifier(name)),
+            Token::Identifier(name) => Ok(Expression::Identifier(IdentifierExpression::new(name))),
             Token::Int(value) => Ok(Expression::Literal(Literal::Int(value))),
             Token::True => Ok(Literal::True.into()),
             Token::False => Ok(Literal::False.into()),
@@ -106,7 +161,6 @@ impl TokenParser {
             Token::Nil => Ok(Literal::Nil.into()),
             Token::Bang => self.parse_unary_expression(UnaryOperator::Not),
             Token::Minus => self.parse_unary_expression(UnaryOperator::Minus),
-            Token::LParen => self.parse_grouped_expression(),
             Token::If => self.parse_if_expression(),
             Token::Function => self.parse_fn_expression(),
             Token::LBracket => self.parse_array_literal(),
@@ -123,6 +177,7 @@ impl TokenParser {
             | Token::Lte
             | Token::Gt
             | Token::Gte
+            | Token::LParen
             | Token::Comma
             | Token::Colon
             | Token::Semicolon
@@ -160,7 +215,7 @@ impl TokenParser {
 
             arguments.push((key, value));
 
-            if self.next_if_eq(&Token::Comma).is_none() {
+            if self.next_if_eq(&Token::Comma).is_none() || self.peek() == Some(&Token::RBrace) {
                 break;
             }
         }
@@ -224,7 +279,8 @@ impl TokenParser {
         let body = self.parse_block()?;
         Ok(Expression::Function(FunctionExpression {
             parameters,
-            body,
+            body: Rc::new(body),
+            source: None,
         }))
     }
 
@@ -254,7 +310,8 @@ impl TokenParser {
         }
         loop {
             arguments.push(self.try_parse()?);
-            if self.next_if_eq(&Token::Comma).is_none() {
+            //a trailing comma is allowed before the closing token
+            if self.next_if_eq(&Token::Comma).is_none() || self.peek() == Some(end) {
                 break;
             }
         }
@@ -272,7 +329,7 @@ impl TokenParser {
 
         loop {
             parameters.push(self.try_ident()?);
-            if self.next_if_eq(&Token::Comma).is_none() {
+            if self.next_if_eq(&Token::Comma).is_none() || self.peek() == Some(&Token::RParen) {
                 break;
             }
         }
@@ -283,12 +340,14 @@ impl TokenParser {
 
     #[inline]
     fn try_next(&mut self) -> Result<Token> {
-        self.next()
-            .ok_or(anyhow!("Unexpected end of file, no more tokens"))
+        self.next().ok_or_else(|| UnexpectedEof(None).into())
     }
 
     #[inline]
     fn try_eat(&mut self, expect: &Token) -> Result<()> {
+        if self.peek().is_none() {
+            return Err(UnexpectedEof(Some(expect.clone())).into());
+        }
         ensure!(
             self.next_if_eq(expect).is_some(),
             "Expected token {:?} but found {:?}",
@@ -0,0 +1,331 @@
+use std::rc::Rc;
+
+use crate::ast::{
+    BinaryExpression, BlockStatement, CallExpression, Expression, FunctionExpression,
+    IdentifierExpression, IfExpression, IndexExpression, Literal, Statement, UnaryExpression,
+};
+
+/// Walks the AST by reference. Every method visits the children of its node by default, so
+/// an implementation only overrides the nodes it cares about and calls the matching `walk_*`
+/// function when it still wants to go deeper.
+pub trait Visitor {
+    fn visit_program(&mut self, statements: &[Statement]) {
+        statements.iter().for_each(|x| self.visit_statement(x));
+    }
+
+    fn visit_statement(&mut self, statement: &Statement) {
+        walk_statement(self, statement);
+    }
+
+    fn visit_block(&mut self, block: &BlockStatement) {
+        walk_block(self, block);
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        walk_expression(self, expression);
+    }
+
+    fn visit_literal(&mut self, literal: &Literal) {
+        walk_literal(self, literal);
+    }
+
+    fn visit_identifier(&mut self, identifier: &IdentifierExpression) {}
+
+    fn visit_unary(&mut self, expression: &UnaryExpression) {
+        walk_unary(self, expression);
+    }
+
+    fn visit_binary(&mut self, expression: &BinaryExpression) {
+        walk_binary(self, expression);
+    }
+
+    fn visit_if(&mut self, expression: &IfExpression) {
+        walk_if(self, expression);
+    }
+
+    fn visit_index(&mut self, expression: &IndexExpression) {
+        walk_index(self, expression);
+    }
+
+    fn visit_function(&mut self, function: &FunctionExpression) {
+        walk_function(self, function);
+    }
+
+    fn visit_call(&mut self, expression: &CallExpression) {
+        walk_call(self, expression);
+    }
+}
+
+pub fn walk_statement<V: Visitor + ?Sized>(visitor: &mut V, statement: &Statement) {
+    match statement {
+        Statement::Let { value, .. } => visitor.visit_expression(value),
+        Statement::Return(value) | Statement::Expression(value) => visitor.visit_expression(value),
+    }
+}
+
+pub fn walk_block<V: Visitor + ?Sized>(visitor: &mut V, block: &BlockStatement) {
+    block.0.iter().for_each(|x| visitor.visit_statement(x));
+}
+
+pub fn walk_expression<V: Visitor + ?Sized>(visitor: &mut V, expression: &Expression) {
+    match expression {
+        Expression::Literal(literal) => visitor.visit_literal(literal),
+        Expression::Identifier(identifier) => visitor.visit_identifier(identifier),
+        Expression::BinaryExp(exp) => visitor.visit_binary(exp),
+        Expression::UnaryExpression(exp) => visitor.visit_unary(exp),
+        Expression::If(exp) => visitor.visit_if(exp),
+        Expression::IndexExpression(exp) => visitor.visit_index(exp),
+        Expression::Function(function) => visitor.visit_function(function),
+        Expression::Call(exp) => visitor.visit_call(exp),
+    }
+}
+
+pub fn walk_literal<V: Visitor + ?Sized>(visitor: &mut V, literal: &Literal) {
+    match literal {
+        Literal::Array(array) => array.iter().for_each(|x| visitor.visit_expression(x)),
+        Literal::Hash(hash) => hash.iter().for_each(|(key, value)| {
+            visitor.visit_expression(key);
+            visitor.visit_expression(value);
+        }),
+        Literal::Int(_) | Literal::String(_) | Literal::True | Literal::False | Literal::Nil => {}
+    }
+}
+
+pub fn walk_unary<V: Visitor + ?Sized>(visitor: &mut V, expression: &UnaryExpression) {
+    visitor.visit_expression(&expression.value);
+}
+
+pub fn walk_binary<V: Visitor + ?Sized>(visitor: &mut V, expression: &BinaryExpression) {
+    visitor.visit_expression(&expression.lhs);
+    visitor.visit_expression(&expression.rhs);
+}
+
+pub fn walk_if<V: Visitor + ?Sized>(visitor: &mut V, expression: &IfExpression) {
+    visitor.visit_expression(&expression.condition);
+    visitor.visit_block(&expression.consequence);
+    if let Some(alternative) = &expression.alternative {
+        visitor.visit_block(alternative);
+    }
+}
+
+pub fn walk_index<V: Visitor + ?Sized>(visitor: &mut V, expression: &IndexExpression) {
+    visitor.visit_expression(&expression.container);
+    visitor.visit_expression(&expression.index);
+}
+
+pub fn walk_function<V: Visitor + ?Sized>(visitor: &mut V, function: &FunctionExpression) {
+    visitor.visit_block(&function.body);
+}
+
+pub fn walk_call<V: Visitor + ?Sized>(visitor: &mut V, expression: &CallExpression) {
+    visitor.visit_expression(&expression.function);
+    expression
+        .arguments
+        .iter()
+        .for_each(|x| visitor.visit_expression(x));
+}
+
+/// Same as `Visitor`, but with mutable access to every node so a pass can rewrite the tree
+/// in place.
+pub trait VisitorMut {
+    fn visit_program_mut(&mut self, statements: &mut [Statement]) {
+        statements
+            .iter_mut()
+            .for_each(|x| self.visit_statement_mut(x));
+    }
+
+    fn visit_statement_mut(&mut self, statement: &mut Statement) {
+        walk_statement_mut(self, statement);
+    }
+
+    fn visit_block_mut(&mut self, block: &mut BlockStatement) {
+        walk_block_mut(self, block);
+    }
+
+    fn visit_expression_mut(&mut self, expression: &mut Expression) {
+        walk_expression_mut(self, expression);
+    }
+
+    fn visit_literal_mut(&mut self, literal: &mut Literal) {
+        walk_literal_mut(self, literal);
+    }
+
+    fn visit_identifier_mut(&mut self, identifier: &mut IdentifierExpression) {}
+
+    fn visit_unary_mut(&mut self, expression: &mut UnaryExpression) {
+        walk_unary_mut(self, expression);
+    }
+
+    fn visit_binary_mut(&mut self, expression: &mut BinaryExpression) {
+        walk_binary_mut(self, expression);
+    }
+
+    fn visit_if_mut(&mut self, expression: &mut IfExpression) {
+        walk_if_mut(self, expression);
+    }
+
+    fn visit_index_mut(&mut self, expression: &mut IndexExpression) {
+        walk_index_mut(self, expression);
+    }
+
+    fn visit_function_mut(&mut self, function: &mut FunctionExpression) {
+        walk_function_mut(self, function);
+    }
+
+    fn visit_call_mut(&mut self, expression: &mut CallExpression) {
+        walk_call_mut(self, expression);
+    }
+}
+
+pub fn walk_statement_mut<V: VisitorMut + ?Sized>(visitor: &mut V, statement: &mut Statement) {
+    match statement {
+        Statement::Let { value, .. } => visitor.visit_expression_mut(value),
+        Statement::Return(value) | Statement::Expression(value) => {
+            visitor.visit_expression_mut(value)
+        }
+    }
+}
+
+pub fn walk_block_mut<V: VisitorMut + ?Sized>(visitor: &mut V, block: &mut BlockStatement) {
+    block
+        .0
+        .iter_mut()
+        .for_each(|x| visitor.visit_statement_mut(x));
+}
+
+pub fn walk_expression_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expression: &mut Expression) {
+    match expression {
+        Expression::Literal(literal) => visitor.visit_literal_mut(literal),
+        Expression::Identifier(identifier) => visitor.visit_identifier_mut(identifier),
+        Expression::BinaryExp(exp) => visitor.visit_binary_mut(exp),
+        Expression::UnaryExpression(exp) => visitor.visit_unary_mut(exp),
+        Expression::If(exp) => visitor.visit_if_mut(exp),
+        Expression::IndexExpression(exp) => visitor.visit_index_mut(exp),
+        Expression::Function(function) => visitor.visit_function_mut(function),
+        Expression::Call(exp) => visitor.visit_call_mut(exp),
+    }
+}
+
+pub fn walk_literal_mut<V: VisitorMut + ?Sized>(visitor: &mut V, literal: &mut Literal) {
+    match literal {
+        Literal::Array(array) => array
+            .iter_mut()
+            .for_each(|x| visitor.visit_expression_mut(x)),
+        Literal::Hash(hash) => hash.iter_mut().for_each(|(key, value)| {
+            visitor.visit_expression_mut(key);
+            visitor.visit_expression_mut(value);
+        }),
+        Literal::Int(_) | Literal::String(_) | Literal::True | Literal::False | Literal::Nil => {}
+    }
+}
+
+pub fn walk_unary_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expression: &mut UnaryExpression) {
+    visitor.visit_expression_mut(&mut expression.value);
+}
+
+pub fn walk_binary_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expression: &mut BinaryExpression) {
+    visitor.visit_expression_mut(&mut expression.lhs);
+    visitor.visit_expression_mut(&mut expression.rhs);
+}
+
+pub fn walk_if_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expression: &mut IfExpression) {
+    visitor.visit_expression_mut(&mut expression.condition);
+    visitor.visit_block_mut(&mut expression.consequence);
+    if let Some(alternative) = &mut expression.alternative {
+        visitor.visit_block_mut(alternative);
+    }
+}
+
+pub fn walk_index_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expression: &mut IndexExpression) {
+    visitor.visit_expression_mut(&mut expression.container);
+    visitor.visit_expression_mut(&mut expression.index);
+}
+
+//the body is cloned first if a function object still shares it
+pub fn walk_function_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    function: &mut FunctionExpression,
+) {
+    visitor.visit_block_mut(Rc::make_mut(&mut function.body));
+}
+
+pub fn walk_call_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expression: &mut CallExpression) {
+    visitor.visit_expression_mut(&mut expression.function);
+    expression
+        .arguments
+        .iter_mut()
+        .for_each(|x| visitor.visit_expression_mut(x));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser, printer::Printer, token::Identifier};
+
+    fn parse(input: &str) -> Vec<Statement> {
+        let parser = Parser::new(Lexer::new(input));
+        assert!(parser.errors.is_empty(), "errors: {:#?}", parser.errors);
+        parser.nodes
+    }
+
+    #[derive(Default)]
+    struct Identifiers {
+        names: Vec<&'static str>,
+        functions: usize,
+    }
+
+    impl Visitor for Identifiers {
+        fn visit_identifier(&mut self, identifier: &IdentifierExpression) {
+            self.names.push(identifier.ident.as_str());
+        }
+
+        fn visit_function(&mut self, function: &FunctionExpression) {
+            self.functions += 1;
+            walk_function(self, function);
+        }
+    }
+
+    #[test]
+    fn visit_every_node() {
+        let input = r#"
+            let f = fn(x) { if (x) { [a, {b: c}] } else { -d[e] } };
+            return f(g + h, fn() { i });
+        "#;
+
+        let mut visitor = Identifiers::default();
+        visitor.visit_program(&parse(input));
+        assert_eq!(
+            visitor.names,
+            ["x", "a", "b", "c", "d", "e", "f", "g", "h", "i"]
+        );
+        assert_eq!(visitor.functions, 2);
+    }
+
+    struct Rename;
+
+    impl VisitorMut for Rename {
+        fn visit_identifier_mut(&mut self, identifier: &mut IdentifierExpression) {
+            identifier.ident = Identifier::new(&identifier.ident.as_str().to_uppercase());
+        }
+
+        fn visit_literal_mut(&mut self, literal: &mut Literal) {
+            if let Literal::Int(int) = literal {
+                *int *= 10;
+            }
+            walk_literal_mut(self, literal);
+        }
+    }
+
+    #[test]
+    fn rewrite_nodes_in_place() {
+        let mut statements = parse("let f = fn(x) { x + [1, y] }; f(2)");
+        Rename.visit_program_mut(&mut statements);
+
+        let mut printer = Printer::new();
+        printer.print_program(&statements);
+        assert_eq!(
+            printer.finish(),
+            "let f = fn(x) {\n    X + [10, Y]\n};\nF(20);\n"
+        );
+    }
+}
@@ -0,0 +1,349 @@
+use std::iter;
+use std::rc::Rc;
+
+use anyhow::{anyhow, bail, ensure, Result};
+
+use crate::code::Opcode;
+use crate::compiler::{Bytecode, CompiledFunction, Constant};
+use crate::object::{Array, BuiltInFn, Closure, EvalError, Object, FALSE, NIL, TRUE};
+
+//the elements of an array or hash literal are all on the stack before it is built, so the
+//compiler rejects literals with more values than this
+pub(crate) const STACK_SIZE: usize = 2048;
+const MAX_FRAMES: usize = 1024;
+
+#[derive(Debug)]
+struct Frame {
+    closure: Rc<Closure>,
+    ip: usize,
+    base_pointer: usize,
+}
+
+impl Frame {
+    fn new(closure: Rc<Closure>, base_pointer: usize) -> Self {
+        Self {
+            closure,
+            ip: 0,
+            base_pointer,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Vm {
+    pub globals: Vec<Option<Object>>,
+    stack: Vec<Object>,
+    frames: Vec<Frame>,
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs the compiled program, keeping globals around for the next call.
+    pub fn run(&mut self, bytecode: Bytecode) -> Result<Object> {
+        let main = CompiledFunction {
+            instructions: bytecode.instructions,
+            num_locals: 0,
+            num_parameters: 0,
+            source: None,
+        };
+        self.stack.clear();
+        self.frames.clear();
+        self.frames
+            .push(Frame::new(Rc::new(Closure::new(Rc::new(main), vec![])), 0));
+        self.execute(&bytecode.constants)
+    }
+
+    fn execute(&mut self, constants: &[Constant]) -> Result<Object> {
+        loop {
+            let frame = self.frames.last_mut().expect("at least the main frame");
+            let instructions = &frame.closure.function.instructions;
+            let Some(byte) = instructions.get(frame.ip) else {
+                //only the main frame can run off its end, every function ends with `ReturnValue`
+                return Ok(self.stack.pop().unwrap_or(NIL));
+            };
+            let op = Opcode::try_from(*byte)?;
+            let operand = match op.operand_widths().first() {
+                Some(2) => instructions.read_u16(frame.ip + 1) as usize,
+                Some(1) => instructions.read_u8(frame.ip + 1) as usize,
+                _ => 0,
+            };
+            frame.ip += op.width();
+            let base_pointer = frame.base_pointer;
+
+            match op {
+                Opcode::Constant => {
+                    let value = match constants.get(operand) {
+                        Some(Constant::Int(int)) => Object::Int(*int),
+                        Some(Constant::String(string)) => Object::String(string.clone()),
+                        Some(Constant::Function(_)) => {
+                            bail!("function constants must be closed over")
+                        }
+                        None => bail!("invalid constant {}", operand),
+                    };
+                    self.push(value)?
+                }
+                Opcode::Pop => {
+                    self.pop()?;
+                }
+                Opcode::True => self.push(TRUE)?,
+                Opcode::False => self.push(FALSE)?,
+                Opcode::Nil => self.push(NIL)?,
+                Opcode::Add
+                | Opcode::Sub
+                | Opcode::Mul
+                | Opcode::Div
+                | Opcode::Eq
+                | Opcode::NotEq
+                | Opcode::Lt
+                | Opcode::Lte
+                | Opcode::Gt
+                | Opcode::Gte => {
+                    let rhs = self.pop()?;
+                    let lhs = self.pop()?;
+                    let result = match op {
+                        Opcode::Add => lhs.add(rhs)?,
+                        Opcode::Sub => lhs.sub(rhs)?,
+                        Opcode::Mul => lhs.mul(rhs)?,
+                        Opcode::Div => lhs.div(rhs)?,
+                        Opcode::Eq => lhs.eq(rhs).into(),
+                        Opcode::NotEq => lhs.not_eq(rhs).into(),
+                        Opcode::Lt => lhs.lt(rhs).into(),
+                        Opcode::Lte => lhs.lte(rhs).into(),
+                        Opcode::Gt => lhs.gt(rhs).into(),
+                        Opcode::Gte => lhs.gte(rhs).into(),
+                        _ => unreachable!(),
+                    };
+                    self.push(result)?
+                }
+                Opcode::Minus => {
+                    let operand = self.pop()?.minus()?;
+                    self.push(operand)?
+                }
+                Opcode::Bang => {
+                    let operand = self.pop()?.not()?;
+                    self.push(operand)?
+                }
+                Opcode::Jump => self.current_frame().ip = operand,
+                Opcode::JumpNotTruthy => {
+                    if !self.pop()?.into_bool()? {
+                        self.current_frame().ip = operand;
+                    }
+                }
+                Opcode::GetGlobal => {
+                    //a hoisted global can be read by a function called before its `let`
+                    let value = self.globals.get(operand).cloned().flatten();
+                    let value = value.ok_or_else(|| anyhow!("global {} is not set", operand))?;
+                    self.push(value)?
+                }
+                Opcode::SetGlobal => {
+                    if self.globals.len() <= operand {
+                        self.globals.resize(operand + 1, None);
+                    }
+                    self.globals[operand] = Some(self.pop()?);
+                }
+                Opcode::GetLocal => {
+                    let value = self.local(base_pointer, operand)?.clone();
+                    self.push(value)?
+                }
+                Opcode::SetLocal => {
+                    let value = self.pop()?;
+                    *self.local(base_pointer, operand)? = value;
+                }
+                Opcode::GetBuiltin => {
+                    let builtin = BuiltInFn::ALL
+                        .get(operand)
+                        .cloned()
+                        .ok_or(anyhow!("Unknown builtin {}", operand))?;
+                    self.push(Object::BuiltInFn(builtin))?
+                }
+                Opcode::GetFree => {
+                    let free = &self.current_frame().closure.free;
+                    let value = free.get(operand).cloned();
+                    self.push(value.ok_or_else(|| anyhow!("invalid free variable {}", operand))?)?
+                }
+                Opcode::CurrentClosure => {
+                    let closure = self.current_frame().closure.clone();
+                    self.push(Object::Closure(closure))?
+                }
+                Opcode::Array => {
+                    let elements = self.pop_many(operand)?;
+                    self.push(Array::new(elements).into())?
+                }
+                Opcode::Hash => {
+                    let elements = self.pop_many(operand)?;
+                    let mut elements = elements.into_iter();
+                    let hash = iter::from_fn(|| elements.next().zip(elements.next())).collect();
+                    self.push(Object::HashTable(Box::new(hash)))?
+                }
+                Opcode::Index => {
+                    let index = self.pop()?;
+                    let container = self.pop()?;
+                    self.push(container.index(index)?)?
+                }
+                Opcode::Call => self.call(operand)?,
+                Opcode::ReturnValue => {
+                    let value = self.pop()?;
+                    let frame = self.frames.pop().expect("returning from a frame");
+                    if self.frames.is_empty() {
+                        return Ok(value);
+                    }
+                    self.stack.truncate(frame.base_pointer - 1);
+                    self.push(value)?
+                }
+                Opcode::Closure => {
+                    let frame = self.current_frame();
+                    let num_free = frame.closure.function.instructions.read_u8(frame.ip - 1);
+                    let Some(Constant::Function(function)) = constants.get(operand) else {
+                        bail!("expected a function constant at {}", operand);
+                    };
+                    let free = self.pop_many(num_free as usize)?;
+                    let closure = Closure::new(function.clone(), free);
+                    self.push(Object::Closure(Rc::new(closure)))?
+                }
+            }
+        }
+    }
+
+    fn call(&mut self, num_args: usize) -> Result<()> {
+        let callee_position = self
+            .stack
+            .len()
+            .checked_sub(num_args + 1)
+            .ok_or_else(|| anyhow!("stack underflow"))?;
+        match &self.stack[callee_position] {
+            Object::Closure(closure) => {
+                let closure = closure.clone();
+                let expected = closure.function.num_parameters;
+                if num_args != expected {
+                    Err(EvalError::ArgumentCountError {
+                        expected,
+                        found: num_args,
+                    })?
+                }
+                let base_pointer = callee_position + 1;
+                ensure!(
+                    self.frames.len() < MAX_FRAMES
+                        && closure.function.num_locals <= STACK_SIZE - base_pointer,
+                    "stack overflow"
+                );
+                self.stack
+                    .resize(base_pointer + closure.function.num_locals, NIL);
+                self.frames.push(Frame::new(closure, base_pointer));
+                Ok(())
+            }
+            Object::BuiltInFn(builtin) => {
+                let builtin = *builtin;
+                let args = self.stack.split_off(callee_position + 1);
+                self.pop()?;
+                self.push(builtin.call(args)?)
+            }
+            value => Err(anyhow!("expected a function, found: {value}")),
+        }
+    }
+
+    #[inline]
+    fn current_frame(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("at least the main frame")
+    }
+
+    #[inline]
+    fn push(&mut self, value: Object) -> Result<()> {
+        ensure!(self.stack.len() < STACK_SIZE, "stack overflow");
+        self.stack.push(value);
+        Ok(())
+    }
+
+    //the compiler always balances the stack, but bytecode files could be crafted not to
+    #[inline]
+    fn pop(&mut self) -> Result<Object> {
+        self.stack.pop().ok_or_else(|| anyhow!("stack underflow"))
+    }
+
+    fn pop_many(&mut self, count: usize) -> Result<Vec<Object>> {
+        ensure!(count <= self.stack.len(), "stack underflow");
+        Ok(self.stack.split_off(self.stack.len() - count))
+    }
+
+    fn local(&mut self, base_pointer: usize, index: usize) -> Result<&mut Object> {
+        self.stack
+            .get_mut(base_pointer + index)
+            .ok_or_else(|| anyhow!("invalid local {}", index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::tests::{outcome, CASES};
+    use crate::eval::Program;
+    use crate::{compiler::Compiler, lexer::Lexer, parser::Parser};
+
+    fn run(input: &str) -> Result<Object> {
+        let lexer = Lexer::new(input);
+        let parser = Parser::new(lexer);
+        let bytecode = Compiler::new().compile(parser)?;
+        Vm::new().run(bytecode)
+    }
+
+    //the snapshots of `eval::tests` then hold for both backends
+    #[test]
+    fn vm_agrees_with_eval() {
+        for (_, inputs) in CASES {
+            for input in inputs.iter() {
+                let expected = outcome(Program::new().eval(Parser::new(Lexer::new(input))));
+                assert_eq!(outcome(run(input)), expected, "{}", input);
+            }
+        }
+    }
+
+    #[test]
+    fn vm_keeps_globals_between_runs() {
+        let mut compiler = Compiler::new();
+        let mut vm = Vm::new();
+        let result = ["let a = 1;", "let add = fn(x) { x + a };", "add(a + 1)"]
+            .iter()
+            .map(|x| {
+                let bytecode = compiler.compile(Parser::new(Lexer::new(x))).unwrap();
+                vm.run(bytecode).unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(result, vec![NIL, NIL, Object::Int(3)]);
+    }
+
+    //the tree-walker would overflow the native stack instead
+    #[test]
+    fn vm_runtime_errors() {
+        let error = run("let f = fn() { f() }; f()").unwrap_err();
+        assert_eq!(error.to_string(), "stack overflow");
+    }
+
+    #[test]
+    fn vm_builds_literals_up_to_the_stack_size() {
+        let elements = vec!["1"; STACK_SIZE].join(", ");
+        let input = format!("let a = [{}]; len(a)", elements);
+        assert_eq!(run(&input).unwrap(), Object::Int(STACK_SIZE as i64));
+        //the function being called is already on the stack
+        let input = format!("len([{}])", elements);
+        assert_eq!(run(&input).unwrap_err().to_string(), "stack overflow");
+    }
+
+    #[test]
+    fn vm_calls_globals_defined_later() {
+        let input = "
+            let isEven = fn(n) { if (n == 0) { true } else { isOdd(n - 1) } };
+            let isOdd = fn(n) { if (n == 0) { false } else { isEven(n - 1) } };
+            isEven(10)
+        ";
+        assert_eq!(run(input).unwrap(), TRUE);
+        let error = run("let f = fn() { g }; f(); let g = 1;").unwrap_err();
+        assert_eq!(error.to_string(), "global 1 is not set");
+        assert_eq!(
+            run("let len = fn(x) { 0 }; len([1, 2])").unwrap(),
+            Object::Int(0)
+        );
+    }
+}